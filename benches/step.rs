@@ -0,0 +1,32 @@
+use cellauto3d::rules::Rules;
+use cellauto3d::system::{AutoSystem3d, Noise, Stepping, SystemDims};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// steps of the default rule in systems filled like the one at start-up,
+// one benchmark per way of counting the neighbours
+// the system keeps evolving between iterations, so after the warm-up
+// the steps are timed in the steady state rather than right after filling
+fn step(c: &mut Criterion) {
+    let rules = Rules::parse_from_str("6-8/7/4/M").unwrap();
+    for edge in [64, 128] {
+        let dims = SystemDims::new(edge, edge, edge);
+        let mut group = c.benchmark_group(format!("6-8/7/4/M {edge}x{edge}x{edge}"));
+        group.sample_size(20);
+        for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+            let mut system = AutoSystem3d::new_from_dims(&dims);
+            system.set_stepping(stepping);
+            system.fill_pseudorandom(&dims, 0.1, rules.life(), 42);
+            let mut generation = 0;
+            group.bench_function(format!("{stepping:?}"), |b| {
+                b.iter(|| {
+                    generation += 1;
+                    system.step(&rules, &dims, true, Noise::new(42, generation))
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
@@ -0,0 +1,288 @@
+use crate::boundary::{Boundaries, Neighbour};
+use crate::chunks::Chunks;
+use crate::rules::{Neighbourhood, Rules};
+use crate::system::{Occupancy, SystemDims};
+
+// number of bit slices used for neighbour counts, enough for any count
+// up to MAX_COUNT and for any configuration of a table, see 'Table'
+pub const COUNT_BITS: usize = 32;
+
+// bit sliced neighbour counts of 64 cells
+// bit b of slice i is bit i of the count of cell b
+pub type Counts = [u64; COUNT_BITS];
+
+// the offset of a row of neighbours, and the offset along z and the
+// weight of each neighbour within that row
+type RowTaps = ((isize, isize), Vec<(isize, usize)>);
+
+// occupancy of the entire system packed into rows of bits along z
+// 64 cells share a single u64, so counting neighbours can be done
+// for 64 cells at once with bitwise adders
+// every row has 'halo' additional cells at both ends, and there are
+// 'halo' additional rows on each side in x and y, where 'halo' is the
+// reach of the neighbourhood, so the cell at (x, y, z) is stored in row
+// (x + halo, y + halo) at bit z + halo
+// the halo is filled according to the boundaries of the system
+#[derive(Debug, Clone)]
+pub struct BitGrid {
+    dims: SystemDims,
+    halo: usize,
+    words: usize,
+    rows: Vec<u64>,
+    // neighbours grouped by row
+    taps: Vec<RowTaps>,
+    // number of bit slices needed for the largest possible count
+    count_bits: usize,
+}
+
+impl BitGrid {
+    // pack the occupancy of cell states, only cells that count as
+    // neighbours are set
+    pub(crate) fn from_chunks(
+        states: &Chunks<u8>,
+        dims: &SystemDims,
+        boundaries: &Boundaries,
+        occupancy: Occupancy,
+        neighbourhood: &Neighbourhood,
+    ) -> Self {
+        let h = neighbourhood.reach();
+        let words = (dims.z() + 2 * h).div_ceil(64);
+        let mut taps: Vec<RowTaps> = Vec::new();
+        for (o, w) in neighbourhood
+            .offsets()
+            .into_iter()
+            .zip(neighbourhood.weights())
+        {
+            match taps.iter_mut().find(|t| t.0 == (o.0, o.1)) {
+                Some(t) => t.1.push((o.2, w)),
+                None => taps.push(((o.0, o.1), vec![(o.2, w)])),
+            }
+        }
+        let mut grid = Self {
+            dims: *dims,
+            halo: h,
+            words,
+            rows: vec![0u64; (dims.x() + 2 * h) * (dims.y() + 2 * h) * words],
+            taps,
+            count_bits: (usize::BITS - neighbourhood.max_count().leading_zeros()) as usize,
+        };
+        let nz = dims.z() as isize;
+        let mut cells = vec![0u8; dims.z()];
+        for r in 0..dims.x() * dims.y() {
+            let (x, y) = (r / dims.y(), r % dims.y());
+            states.read_row((x, y), &mut cells);
+            let row = grid.row_mut(x + h, y + h);
+            for (k, _) in cells
+                .iter()
+                .enumerate()
+                .filter(|(_, &s)| occupancy.counts(s))
+            {
+                row[(k + h) / 64] |= 1 << ((k + h) % 64);
+            }
+            if !boundaries.is_periodic() {
+                continue;
+            }
+            // halo cells along z
+            for b in 0..h {
+                let below = (b as isize - h as isize).rem_euclid(nz) as usize;
+                if occupancy.counts(cells[below]) {
+                    row[b / 64] |= 1 << (b % 64);
+                }
+                let above = b % dims.z();
+                if occupancy.counts(cells[above]) {
+                    let b = dims.z() + h + b;
+                    row[b / 64] |= 1 << (b % 64);
+                }
+            }
+        }
+        if boundaries.is_periodic() {
+            grid.fill_halo_rows();
+        } else {
+            grid.fill_halo(states, boundaries, occupancy);
+        }
+        grid
+    }
+    // set every halo cell according to the boundaries, one at a time
+    fn fill_halo(&mut self, states: &Chunks<u8>, boundaries: &Boundaries, occupancy: Occupancy) {
+        let dims = self.dims;
+        let h = self.halo as isize;
+        let (nx, ny, nz) = (dims.x() as isize, dims.y() as isize, dims.z() as isize);
+        for px in 0..nx + 2 * h {
+            for py in 0..ny + 2 * h {
+                let halo_row = !(h..nx + h).contains(&px) || !(h..ny + h).contains(&py);
+                let bits: Vec<isize> = if halo_row {
+                    (0..nz + 2 * h).collect()
+                } else {
+                    (0..h).chain(nz + h..nz + 2 * h).collect()
+                };
+                for pz in bits {
+                    let occupied = match boundaries.resolve((px - h, py - h, pz - h), &dims) {
+                        Neighbour::Cell(uxyz) => occupancy.counts(states.get(uxyz)),
+                        Neighbour::Empty => false,
+                        Neighbour::Occupied => true,
+                    };
+                    if occupied {
+                        let row = self.row_mut(px as usize, py as usize);
+                        row[pz as usize / 64] |= 1 << (pz % 64);
+                    }
+                }
+            }
+        }
+    }
+    // copy rows of the opposite walls into the halo rows
+    fn fill_halo_rows(&mut self) {
+        let h = self.halo as isize;
+        let (nx, ny) = (self.dims.x() as isize, self.dims.y() as isize);
+        for px in 0..nx + 2 * h {
+            for py in 0..ny + 2 * h {
+                if (h..nx + h).contains(&px) && (h..ny + h).contains(&py) {
+                    continue;
+                }
+                let from = ((px - h).rem_euclid(nx) + h, (py - h).rem_euclid(ny) + h);
+                self.copy_row(
+                    (from.0 as usize, from.1 as usize),
+                    (px as usize, py as usize),
+                );
+            }
+        }
+    }
+    fn copy_row(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let src = self.row_start(from.0, from.1);
+        let dst = self.row_start(to.0, to.1);
+        self.rows.copy_within(src..src + self.words, dst);
+    }
+    fn row_start(&self, px: usize, py: usize) -> usize {
+        (px * (self.dims.y() + 2 * self.halo) + py) * self.words
+    }
+    // padded row coordinates, i.e. (0, 0) is a halo row
+    fn row(&self, px: usize, py: usize) -> &[u64] {
+        let start = self.row_start(px, py);
+        &self.rows[start..start + self.words]
+    }
+    fn row_mut(&mut self, px: usize, py: usize) -> &mut [u64] {
+        let start = self.row_start(px, py);
+        &mut self.rows[start..start + self.words]
+    }
+    // number of words per row
+    pub fn words(&self) -> usize {
+        self.words
+    }
+    // position of cell z within a row: (word, bit)
+    pub fn cell_bit(&self, z: usize) -> (usize, u32) {
+        ((z + self.halo) / 64, ((z + self.halo) % 64) as u32)
+    }
+    // cell z of a row, given word 'w' and bit 'b'
+    pub fn bit_cell(&self, w: usize, b: u32) -> usize {
+        w * 64 + b as usize - self.halo
+    }
+    // occupancy of the cells in row (x, y), word 'w'
+    // use 'cell_bit' to get the position of a cell
+    pub fn occupancy(&self, x: usize, y: usize, w: usize) -> u64 {
+        self.row(x + self.halo, y + self.halo)[w] & self.valid_mask(w)
+    }
+    // bits that belong to actual cells rather than the halo or padding
+    pub fn valid_mask(&self, w: usize) -> u64 {
+        let bits = |end: usize| match end {
+            e if e <= w * 64 => 0,
+            e if e >= (w + 1) * 64 => u64::MAX,
+            e => (1 << (e - w * 64)) - 1,
+        };
+        bits(self.dims.z() + self.halo) & !bits(self.halo)
+    }
+    // neighbour counts of all cells in row (x, y), one entry per word
+    pub fn count_row(&self, x: usize, y: usize, out: &mut Vec<Counts>) {
+        out.clear();
+        out.resize(self.words, [0u64; COUNT_BITS]);
+        let (px, py) = ((x + self.halo) as isize, (y + self.halo) as isize);
+        for ((dx, dy), taps) in &self.taps {
+            let row = self.row((px + dx) as usize, (py + dy) as usize);
+            for (w, acc) in out.iter_mut().enumerate() {
+                for &(dz, weight) in taps {
+                    add_weighted(acc, shifted(row, w, dz), weight);
+                }
+            }
+        }
+    }
+    // neighbour count of the cell at bit 'b'
+    pub fn count(&self, acc: &Counts, b: u32) -> usize {
+        (0..self.count_bits).fold(0, |n, i| n | ((acc[i] >> b & 1) as usize) << i)
+    }
+    // select all cells whose neighbour count is contained in 'set', see
+    // 'count_set', 'table' tells for every count whether it is contained
+    pub fn select(&self, acc: &Counts, set: Option<&[usize]>, table: &[bool]) -> u64 {
+        // large sets, e.g. of directional rules, are cheaper to look up
+        // cell by cell
+        let Some(set) = set else {
+            return (0..64).fold(0u64, |selected, b| match table.get(self.count(acc, b)) {
+                Some(true) => selected | 1 << b,
+                _ => selected,
+            });
+        };
+        let mut selected = 0u64;
+        for n in set {
+            let mut eq = u64::MAX;
+            for (i, slice) in acc.iter().take(self.count_bits).enumerate() {
+                eq &= if n & (1 << i) != 0 { *slice } else { !*slice };
+            }
+            selected |= eq;
+        }
+        selected
+    }
+}
+
+// word w of a row where every bit holds the value of its neighbour at
+// z + s, where s is less than 64 cells away
+fn shifted(row: &[u64], w: usize, s: isize) -> u64 {
+    match s {
+        0 => row[w],
+        s if s > 0 => {
+            let carry = if w + 1 < row.len() {
+                row[w + 1] << (64 - s)
+            } else {
+                0
+            };
+            (row[w] >> s) | carry
+        }
+        s => {
+            let carry = if w > 0 { row[w - 1] >> (64 + s) } else { 0 };
+            (row[w] << -s) | carry
+        }
+    }
+}
+
+// add 'weight' to each of the 64 bit sliced counters whose bit is set
+fn add_weighted(acc: &mut Counts, bits: u64, mut weight: usize) {
+    while weight != 0 {
+        let mut carry = bits;
+        for slice in acc[weight.trailing_zeros() as usize..].iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            let next = *slice & carry;
+            *slice ^= carry;
+            carry = next;
+        }
+        weight &= weight - 1;
+    }
+}
+
+// all neighbour counts up to 'max' that meet a condition, or none if
+// there are more than 64 of them or if the rules use a table, as these
+// are cheaper to look up cell by cell
+pub fn count_set(
+    contains: impl Fn(usize) -> bool,
+    max: usize,
+    rules: &Rules,
+) -> Option<Vec<usize>> {
+    if rules.table().is_some() {
+        return None;
+    }
+    let mut set = Vec::new();
+    for n in (0..=max).filter(|&n| contains(n)) {
+        if set.len() == 64 {
+            return None;
+        }
+        set.push(n);
+    }
+    Some(set)
+}
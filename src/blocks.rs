@@ -0,0 +1,132 @@
+use crate::system::SystemDims;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// edge length of a block in cells
+pub const BLOCK: usize = 8;
+
+// divides the system into blocks of BLOCK x BLOCK x BLOCK cells and keeps
+// track of the blocks that changed since the previous step
+// a block that did not change, and whose neighbourhood did not change
+// either, will not change during the next step, so there is no need to
+// evaluate it
+// blocks at the far walls may be smaller if the system size is not a
+// multiple of BLOCK
+#[derive(Debug, Clone)]
+pub struct Blocks {
+    dims: SystemDims,
+    counts: (usize, usize, usize),
+    changed: Vec<bool>,
+}
+
+impl Blocks {
+    // initially every block is considered to be changed
+    pub fn new(dims: &SystemDims) -> Self {
+        let counts = (
+            dims.x().div_ceil(BLOCK),
+            dims.y().div_ceil(BLOCK),
+            dims.z().div_ceil(BLOCK),
+        );
+        Self {
+            dims: *dims,
+            counts,
+            changed: vec![true; counts.0 * counts.1 * counts.2],
+        }
+    }
+    // number of blocks along each axis
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.counts
+    }
+    pub fn len(&self) -> usize {
+        self.changed.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+    }
+    pub fn index(&self, b: (usize, usize, usize)) -> usize {
+        (b.0 * self.counts.1 + b.1) * self.counts.2 + b.2
+    }
+    // block that contains the given cell
+    pub fn block_of(uxyz: (usize, usize, usize)) -> (usize, usize, usize) {
+        (uxyz.0 / BLOCK, uxyz.1 / BLOCK, uxyz.2 / BLOCK)
+    }
+    // cells covered by block 'b' along an axis of length 'len'
+    pub fn cells(b: usize, len: usize) -> std::ops::Range<usize> {
+        b * BLOCK..((b + 1) * BLOCK).min(len)
+    }
+    pub fn mark(&mut self, uxyz: (usize, usize, usize)) {
+        let idx = self.index(Self::block_of(uxyz));
+        self.changed[idx] = true;
+    }
+    pub fn mark_all(&mut self) {
+        self.changed.fill(true);
+    }
+    pub fn is_changed(&self, b: (usize, usize, usize)) -> bool {
+        self.changed[self.index(b)]
+    }
+    // all changed blocks
+    pub fn changed(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let (_, ny, nz) = self.counts;
+        self.changed
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c)
+            .map(move |(idx, _)| (idx / (ny * nz), idx / nz % ny, idx % nz))
+    }
+    // mark the blocks that changed in 'other' as well
+    pub fn include(&mut self, other: &Self) {
+        for (changed, &other) in self.changed.iter_mut().zip(&other.changed) {
+            *changed |= other;
+        }
+    }
+    // replace the changed blocks by the ones recorded during a step
+    pub fn record(&mut self, changed: Vec<AtomicBool>) {
+        self.changed = changed.into_iter().map(AtomicBool::into_inner).collect();
+    }
+    // flags for recording changes concurrently, see 'record'
+    pub fn recorder(&self) -> Vec<AtomicBool> {
+        (0..self.len()).map(|_| AtomicBool::new(false)).collect()
+    }
+    pub fn record_change(&self, recorder: &[AtomicBool], uxyz: (usize, usize, usize)) {
+        recorder[self.index(Self::block_of(uxyz))].store(true, Ordering::Relaxed);
+    }
+    // blocks that have to be evaluated during the next step, i.e. every
+    // changed block and all blocks within 'reach' cells of it
+    // neighbourhoods wrap around at the walls
+    pub fn active(&self, reach: usize) -> Vec<bool> {
+        let radius = (
+            axis_radius(reach, self.dims.x()),
+            axis_radius(reach, self.dims.y()),
+            axis_radius(reach, self.dims.z()),
+        );
+        let mut active = vec![false; self.len()];
+        for b in self.changed() {
+            for dx in -radius.0..=radius.0 {
+                for dy in -radius.1..=radius.1 {
+                    for dz in -radius.2..=radius.2 {
+                        let n = (
+                            wrap(b.0, dx, self.counts.0),
+                            wrap(b.1, dy, self.counts.1),
+                            wrap(b.2, dz, self.counts.2),
+                        );
+                        active[self.index(n)] = true;
+                    }
+                }
+            }
+        }
+        active
+    }
+}
+
+// number of blocks a neighbourhood of 'reach' cells may span along an
+// axis of length 'len', the last block may be smaller than the others
+fn axis_radius(reach: usize, len: usize) -> isize {
+    let smallest = match len % BLOCK {
+        0 => BLOCK,
+        rest => rest,
+    };
+    reach.div_ceil(smallest) as isize
+}
+
+fn wrap(b: usize, d: isize, count: usize) -> usize {
+    (b as isize + d).rem_euclid(count as isize) as usize
+}
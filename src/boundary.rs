@@ -0,0 +1,335 @@
+use crate::system::SystemDims;
+use std::fmt;
+
+// what happens to neighbours that lie beyond a wall of the system
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Boundary {
+    // jump back to the opposite wall
+    #[default]
+    Wrap,
+    // everything beyond the wall is empty
+    Dead,
+    // everything beyond the wall is occupied
+    Alive,
+    // the wall acts as a mirror, i.e. the neighbour beyond the wall is
+    // the cell on this side of it
+    Mirror,
+    // jump back to the opposite wall, flipping the next axis
+    // (x flips y, y flips z, z flips x)
+    Twisted,
+    // jump back to the opposite wall, shifting the next axis by half
+    // its length
+    Shifted,
+}
+
+impl Boundary {
+    pub fn parse_from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "wrap" | "periodic" | "torus" => Some(Self::Wrap),
+            "dead" => Some(Self::Dead),
+            "alive" => Some(Self::Alive),
+            "mirror" | "reflect" => Some(Self::Mirror),
+            "twisted" | "flip" => Some(Self::Twisted),
+            "shifted" | "shift" => Some(Self::Shifted),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Wrap => "wrap",
+            Self::Dead => "dead",
+            Self::Alive => "alive",
+            Self::Mirror => "mirror",
+            Self::Twisted => "twisted",
+            Self::Shifted => "shifted",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// a neighbour of a cell, after applying the boundaries
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Neighbour {
+    Cell((usize, usize, usize)),
+    Empty,
+    Occupied,
+}
+
+// boundary of every axis, defaults to a torus
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Boundaries {
+    pub x: Boundary,
+    pub y: Boundary,
+    pub z: Boundary,
+}
+
+impl Boundaries {
+    pub fn new(x: Boundary, y: Boundary, z: Boundary) -> Self {
+        Self { x, y, z }
+    }
+    pub fn all(boundary: Boundary) -> Self {
+        Self::new(boundary, boundary, boundary)
+    }
+    fn axes(&self) -> [Boundary; 3] {
+        [self.x, self.y, self.z]
+    }
+    // every axis wraps without any twist
+    pub fn is_periodic(&self) -> bool {
+        self.axes().iter().all(|b| *b == Boundary::Wrap)
+    }
+    // some axis connects walls with a twist, i.e. a neighbourhood may
+    // continue at a different position along another axis
+    pub fn is_twisted(&self) -> bool {
+        self.axes()
+            .iter()
+            .any(|b| matches!(b, Boundary::Twisted | Boundary::Shifted))
+    }
+    // find the neighbour at 'ixyz', which may lie beyond the walls
+    // axes are resolved one after another, twists are applied once every
+    // axis is back within the system
+    pub fn resolve(&self, ixyz: (isize, isize, isize), dims: &SystemDims) -> Neighbour {
+        let lengths = [dims.x() as isize, dims.y() as isize, dims.z() as isize];
+        let mut c = [ixyz.0, ixyz.1, ixyz.2];
+        let mut twists = [None; 3];
+        for (a, boundary) in self.axes().into_iter().enumerate() {
+            let len = lengths[a];
+            if (0..len).contains(&c[a]) {
+                continue;
+            }
+            match boundary {
+                Boundary::Dead => return Neighbour::Empty,
+                Boundary::Alive => return Neighbour::Occupied,
+                Boundary::Mirror => {
+                    let m = c[a].rem_euclid(2 * len);
+                    c[a] = if m < len { m } else { 2 * len - 1 - m };
+                }
+                _ => {
+                    c[a] = c[a].rem_euclid(len);
+                    if boundary != Boundary::Wrap {
+                        twists[(a + 1) % 3] = Some(boundary);
+                    }
+                }
+            }
+        }
+        for (a, twist) in twists.into_iter().enumerate() {
+            let len = lengths[a];
+            match twist {
+                Some(Boundary::Twisted) => c[a] = len - 1 - c[a],
+                Some(Boundary::Shifted) => c[a] = (c[a] + len / 2).rem_euclid(len),
+                _ => {}
+            }
+        }
+        Neighbour::Cell((c[0] as usize, c[1] as usize, c[2] as usize))
+    }
+    // all positions within 'reach' cells beyond the walls, whose
+    // neighbour is a cell of the system
+    pub fn halo(&self, reach: usize, dims: &SystemDims) -> Vec<((isize, isize, isize), usize)> {
+        let r = reach as isize;
+        let (lx, ly, lz) = (dims.x() as isize, dims.y() as isize, dims.z() as isize);
+        let mut halo = Vec::new();
+        let mut push = |p: (isize, isize, isize)| {
+            if let Neighbour::Cell(u) = self.resolve(p, dims) {
+                halo.push((p, (u.0 * dims.y() + u.1) * dims.z() + u.2));
+            }
+        };
+        for px in -r..lx + r {
+            for py in -r..ly + r {
+                if (0..lx).contains(&px) && (0..ly).contains(&py) {
+                    for pz in (-r..0).chain(lz..lz + r) {
+                        push((px, py, pz));
+                    }
+                } else {
+                    for pz in -r..lz + r {
+                        push((px, py, pz));
+                    }
+                }
+            }
+        }
+        halo
+    }
+}
+
+// parse boundaries like "x=wrap,y=dead,z=mirror", axes that are not
+// mentioned wrap around, a single mode without an axis applies to all
+// of them
+impl std::str::FromStr for Boundaries {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = |mode: &str| {
+            format!(
+                "unknown boundary '{mode}', expected one of wrap, dead, alive, mirror, twisted, shifted"
+            )
+        };
+        if !s.contains('=') {
+            return Boundary::parse_from_str(s)
+                .map(Self::all)
+                .ok_or_else(|| unknown(s));
+        }
+        let mut boundaries = Self::default();
+        for part in s.split(',') {
+            let Some((axis, mode)) = part.split_once('=') else {
+                return Err(format!("expected 'axis=mode', got '{part}'"));
+            };
+            let mode = Boundary::parse_from_str(mode).ok_or_else(|| unknown(mode))?;
+            match axis.trim() {
+                "x" | "X" => boundaries.x = mode,
+                "y" | "Y" => boundaries.y = mode,
+                "z" | "Z" => boundaries.z = mode,
+                other => return Err(format!("unknown axis '{other}'")),
+            }
+        }
+        Ok(boundaries)
+    }
+}
+
+impl fmt::Display for Boundaries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x={},y={},z={}", self.x, self.y, self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: usize, y: usize, z: usize) -> Neighbour {
+        Neighbour::Cell((x, y, z))
+    }
+
+    // the index of the cell a halo position refers to, if any
+    fn image(halo: &[((isize, isize, isize), usize)], p: (isize, isize, isize)) -> Option<usize> {
+        halo.iter().find(|h| h.0 == p).map(|h| h.1)
+    }
+
+    #[test]
+    fn from_str() {
+        use Boundary::*;
+        let parse = |s: &str| s.parse::<Boundaries>();
+        assert_eq!(parse("dead"), Ok(Boundaries::all(Dead)));
+        assert_eq!(parse("reflect"), Ok(Boundaries::all(Mirror)));
+        assert_eq!(
+            parse("x=wrap,y=dead,z=mirror"),
+            Ok(Boundaries::new(Wrap, Dead, Mirror))
+        );
+        // axes that are not mentioned wrap around
+        assert_eq!(
+            parse(" Z = shift"),
+            Ok(Boundaries::new(Wrap, Wrap, Shifted))
+        );
+        assert_eq!(
+            parse("y=alive,x=flip"),
+            Ok(Boundaries::new(Twisted, Alive, Wrap))
+        );
+        let boundaries = Boundaries::new(Alive, Twisted, Shifted);
+        assert_eq!(parse(&boundaries.to_string()), Ok(boundaries));
+        for input in ["solid", "", "x=solid", "w=dead", "x=dead,ydead", "x=dead,"] {
+            assert!(parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn resolve_walls() {
+        use Boundary::*;
+        let dims = SystemDims::new(4, 6, 8);
+        let resolve = |boundary: Boundary, x: isize| {
+            Boundaries::new(boundary, Wrap, Wrap).resolve((x, 1, 2), &dims)
+        };
+        for boundary in [Wrap, Dead, Alive, Mirror, Twisted, Shifted] {
+            assert_eq!(resolve(boundary, 0), cell(0, 1, 2), "{boundary}");
+            assert_eq!(resolve(boundary, 3), cell(3, 1, 2), "{boundary}");
+        }
+        for x in [-1, 4, 9, -6] {
+            assert_eq!(resolve(Dead, x), Neighbour::Empty);
+            assert_eq!(resolve(Alive, x), Neighbour::Occupied);
+        }
+        assert_eq!(resolve(Wrap, -1), cell(3, 1, 2));
+        assert_eq!(resolve(Wrap, 4), cell(0, 1, 2));
+        assert_eq!(resolve(Wrap, 9), cell(1, 1, 2));
+        assert_eq!(resolve(Wrap, -6), cell(2, 1, 2));
+        // the mirror image repeats every 2 * len cells
+        assert_eq!(resolve(Mirror, -1), cell(0, 1, 2));
+        assert_eq!(resolve(Mirror, 4), cell(3, 1, 2));
+        assert_eq!(resolve(Mirror, 5), cell(2, 1, 2));
+        assert_eq!(resolve(Mirror, 8), cell(0, 1, 2));
+        assert_eq!(resolve(Mirror, 9), cell(1, 1, 2));
+        assert_eq!(resolve(Mirror, -6), cell(2, 1, 2));
+        // crossing the x walls flips y, or shifts it by 3
+        assert_eq!(resolve(Twisted, -1), cell(3, 4, 2));
+        assert_eq!(resolve(Twisted, 4), cell(0, 4, 2));
+        assert_eq!(resolve(Twisted, 9), cell(1, 4, 2));
+        assert_eq!(resolve(Shifted, -1), cell(3, 4, 2));
+        assert_eq!(resolve(Shifted, 4), cell(0, 4, 2));
+        assert_eq!(resolve(Shifted, -6), cell(2, 4, 2));
+    }
+
+    #[test]
+    fn resolve_twists() {
+        use Boundary::*;
+        let dims = SystemDims::new(4, 6, 8);
+        // z flips x, y shifts z
+        let boundaries = Boundaries::new(Wrap, Shifted, Twisted);
+        assert_eq!(boundaries.resolve((1, 2, 8), &dims), cell(2, 2, 0));
+        assert_eq!(boundaries.resolve((1, -1, 3), &dims), cell(1, 5, 7));
+        assert_eq!(boundaries.resolve((1, 6, 5), &dims), cell(1, 0, 1));
+        // the twist of x applies to y after y has been wrapped
+        let boundaries = Boundaries::new(Twisted, Wrap, Wrap);
+        assert_eq!(boundaries.resolve((-1, 6, 2), &dims), cell(3, 5, 2));
+        assert_eq!(boundaries.resolve((-1, -1, 2), &dims), cell(3, 0, 2));
+        // twists of x and y at once, y flips z
+        let boundaries = Boundaries::new(Twisted, Twisted, Wrap);
+        assert_eq!(boundaries.resolve((-1, -1, 2), &dims), cell(3, 0, 5));
+        // a dead wall along the next axis wins
+        let boundaries = Boundaries::new(Twisted, Dead, Wrap);
+        assert_eq!(boundaries.resolve((-1, 6, 2), &dims), Neighbour::Empty);
+        assert_eq!(boundaries.resolve((-1, 5, 2), &dims), cell(3, 0, 2));
+    }
+
+    #[test]
+    fn halo() {
+        use Boundary::*;
+        let index =
+            |u: (usize, usize, usize), dims: &SystemDims| (u.0 * dims.y() + u.1) * dims.z() + u.2;
+        let dims = SystemDims::new(4, 6, 8);
+        let outside = 6 * 8 * 10 - 4 * 6 * 8;
+        for boundaries in [
+            Boundaries::all(Mirror),
+            Boundaries::new(Twisted, Wrap, Shifted),
+            Boundaries::new(Wrap, Twisted, Twisted),
+        ] {
+            let halo = boundaries.halo(1, &dims);
+            assert_eq!(halo.len(), outside, "{boundaries}");
+            for (p, idx) in &halo {
+                let Neighbour::Cell(u) = boundaries.resolve(*p, &dims) else {
+                    panic!("{boundaries} {p:?}");
+                };
+                assert_eq!(*idx, index(u, &dims), "{boundaries} {p:?}");
+            }
+        }
+        // only positions beyond the walls
+        let halo = Boundaries::all(Mirror).halo(1, &dims);
+        assert_eq!(image(&halo, (0, 0, 0)), None);
+        assert_eq!(image(&halo, (-1, 0, 0)), Some(0));
+        assert_eq!(image(&halo, (4, 6, 8)), Some(index((3, 5, 7), &dims)));
+        // x flips y, z shifts x
+        let halo = Boundaries::new(Twisted, Wrap, Shifted).halo(1, &dims);
+        assert_eq!(image(&halo, (-1, 1, 2)), Some(index((3, 4, 2), &dims)));
+        assert_eq!(image(&halo, (1, 2, 8)), Some(index((3, 2, 0), &dims)));
+        // beyond a dead wall or an occupied wall there are no cells
+        assert!(Boundaries::new(Dead, Alive, Dead).halo(2, &dims).is_empty());
+        assert_eq!(
+            Boundaries::new(Dead, Wrap, Dead).halo(1, &dims).len(),
+            4 * 2 * 8
+        );
+        // a reach beyond the length of an axis reflects more than once
+        let dims = SystemDims::new(2, 2, 2);
+        let halo = Boundaries::all(Mirror).halo(3, &dims);
+        assert_eq!(halo.len(), 8usize.pow(3) - 8);
+        assert_eq!(image(&halo, (-3, 0, 0)), Some(index((1, 0, 0), &dims)));
+        assert_eq!(image(&halo, (4, 1, 1)), Some(index((0, 1, 1), &dims)));
+        assert_eq!(image(&halo, (-2, 3, -1)), Some(index((1, 0, 0), &dims)));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::sparse::{CHUNK, CHUNK_VOLUME};
+use crate::system::SystemDims;
+use rayon::prelude::*;
+
+// a value for every cell of a bounded system, stored in chunks of
+// CHUNK x CHUNK x CHUNK cells
+// a chunk is only allocated once it holds a value other than the default,
+// chunks that are entirely default again are released by 'trim', so
+// empty space takes no memory apart from the table of chunks
+// chunks are laid out x-major, i.e. chunk (cx, cy, cz) comes at
+// (cx * ny + cy) * nz + cz, and so are the cells within a chunk
+// chunks at the far walls stick out of the system if its size is not a
+// multiple of CHUNK, the cells beyond the walls always keep the default
+#[derive(Debug, Clone)]
+pub struct Chunks<T> {
+    dims: SystemDims,
+    counts: (usize, usize, usize),
+    chunks: Vec<Option<Box<[T]>>>,
+}
+
+// position of a cell within its chunk
+fn offset(uxyz: (usize, usize, usize)) -> usize {
+    ((uxyz.0 % CHUNK) * CHUNK + uxyz.1 % CHUNK) * CHUNK + uxyz.2 % CHUNK
+}
+
+// cells of chunk 'c' along an axis of length 'len'
+fn cells(c: usize, len: usize) -> std::ops::Range<usize> {
+    c * CHUNK..((c + 1) * CHUNK).min(len)
+}
+
+impl<T: Copy + Default + PartialEq + Send + Sync> Chunks<T> {
+    // every cell holds the default, no chunk is allocated
+    pub fn new(dims: &SystemDims) -> Self {
+        let counts = (
+            dims.x().div_ceil(CHUNK),
+            dims.y().div_ceil(CHUNK),
+            dims.z().div_ceil(CHUNK),
+        );
+        Self {
+            dims: *dims,
+            counts,
+            chunks: vec![None; counts.0 * counts.1 * counts.2],
+        }
+    }
+    // number of chunks along each axis
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.counts
+    }
+    // number of chunks in memory
+    pub fn allocated(&self) -> usize {
+        self.chunks.iter().filter(|c| c.is_some()).count()
+    }
+    fn index(&self, uxyz: (usize, usize, usize)) -> usize {
+        ((uxyz.0 / CHUNK) * self.counts.1 + uxyz.1 / CHUNK) * self.counts.2 + uxyz.2 / CHUNK
+    }
+    pub fn get(&self, uxyz: (usize, usize, usize)) -> T {
+        match &self.chunks[self.index(uxyz)] {
+            Some(chunk) => chunk[offset(uxyz)],
+            None => T::default(),
+        }
+    }
+    // returns the previous value
+    pub fn set(&mut self, uxyz: (usize, usize, usize), value: T) -> T {
+        let idx = self.index(uxyz);
+        set(&mut self.chunks[idx], offset(uxyz), value)
+    }
+    // the chunk that contains the given cell, and the position of the cell
+    // within it, None if the chunk is not allocated
+    pub fn chunk_of(&self, uxyz: (usize, usize, usize)) -> Option<(&[T], usize)> {
+        self.chunks[self.index(uxyz)]
+            .as_deref()
+            .map(|chunk| (chunk, offset(uxyz)))
+    }
+    // like 'chunk_of', but the chunk is allocated if necessary
+    pub fn chunk_mut(&mut self, uxyz: (usize, usize, usize)) -> (&mut [T], usize) {
+        let idx = self.index(uxyz);
+        let chunk = self.chunks[idx].get_or_insert_with(allocate);
+        (chunk, offset(uxyz))
+    }
+    // copy the row of cells along z at x = i, y = j to 'out', which
+    // holds at least dims.z values
+    pub fn read_row(&self, (i, j): (usize, usize), out: &mut [T]) {
+        for cz in 0..self.counts.2 {
+            let ks = cells(cz, self.dims.z());
+            match &self.chunks[self.index((i, j, ks.start))] {
+                Some(chunk) => {
+                    let start = offset((i, j, 0));
+                    out[ks.clone()].copy_from_slice(&chunk[start..start + ks.len()]);
+                }
+                None => out[ks].fill(T::default()),
+            }
+        }
+    }
+    // all columns of chunks along z, one for every x and y chunk position,
+    // which can be written to concurrently
+    pub fn columns_mut(&mut self) -> impl IndexedParallelIterator<Item = Column<'_, T>> {
+        let (dims, ny) = (self.dims, self.counts.1);
+        self.chunks
+            .par_chunks_mut(self.counts.2)
+            .enumerate()
+            .map(move |(c, chunks)| Column {
+                dims,
+                first: (c / ny * CHUNK, c % ny * CHUNK),
+                chunks,
+            })
+    }
+    // all cells whose value differs from 'other', which has to cover the
+    // same system, chunks that are not allocated in either are skipped
+    pub fn differences<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> impl Iterator<Item = (usize, usize, usize)> + 'a {
+        let (ny, nz) = (self.counts.1, self.counts.2);
+        let dims = self.dims;
+        self.chunks
+            .iter()
+            .zip(&other.chunks)
+            .enumerate()
+            .filter(|(_, (a, b))| a.is_some() || b.is_some())
+            .flat_map(move |(c, (a, b))| {
+                let (cx, cy, cz) = (c / (ny * nz), c / nz % ny, c % nz);
+                cells(cx, dims.x()).flat_map(move |i| {
+                    cells(cy, dims.y()).flat_map(move |j| {
+                        cells(cz, dims.z()).filter_map(move |k| {
+                            let at = |chunk: &Option<Box<[T]>>| {
+                                chunk
+                                    .as_ref()
+                                    .map_or(T::default(), |c| c[offset((i, j, k))])
+                            };
+                            (at(a) != at(b)).then_some((i, j, k))
+                        })
+                    })
+                })
+            })
+    }
+    // release all chunks that only hold the default
+    pub fn trim(&mut self) {
+        trim(&mut self.chunks);
+    }
+}
+
+// a system without any cells, e.g. to take the chunks of another
+impl<T> Default for Chunks<T> {
+    fn default() -> Self {
+        Self {
+            dims: SystemDims::new(0, 0, 0),
+            counts: (0, 0, 0),
+            chunks: Vec::new(),
+        }
+    }
+}
+
+fn allocate<T: Copy + Default>() -> Box<[T]> {
+    vec![T::default(); CHUNK_VOLUME].into_boxed_slice()
+}
+
+fn set<T: Copy + Default + PartialEq>(chunk: &mut Option<Box<[T]>>, idx: usize, value: T) -> T {
+    match chunk {
+        Some(cells) => std::mem::replace(&mut cells[idx], value),
+        None if value == T::default() => value,
+        None => {
+            let mut cells = allocate();
+            cells[idx] = value;
+            *chunk = Some(cells);
+            T::default()
+        }
+    }
+}
+
+fn trim<T: Copy + Default + PartialEq>(chunks: &mut [Option<Box<[T]>>]) {
+    for chunk in chunks {
+        if chunk
+            .as_ref()
+            .is_some_and(|cells| cells.iter().all(|&v| v == T::default()))
+        {
+            *chunk = None;
+        }
+    }
+}
+
+// the chunks of a system that share the same x and y position, handed
+// out by 'Chunks::columns_mut'
+// cells are addressed by their position in the system and have to lie
+// within 'range_x' and 'range_y'
+pub struct Column<'a, T> {
+    dims: SystemDims,
+    // x and y of the first cell
+    first: (usize, usize),
+    chunks: &'a mut [Option<Box<[T]>>],
+}
+
+impl<T: Copy + Default + PartialEq> Column<'_, T> {
+    pub fn range_x(&self) -> std::ops::Range<usize> {
+        cells(self.first.0 / CHUNK, self.dims.x())
+    }
+    pub fn range_y(&self) -> std::ops::Range<usize> {
+        cells(self.first.1 / CHUNK, self.dims.y())
+    }
+    pub fn get(&self, uxyz: (usize, usize, usize)) -> T {
+        debug_assert!(self.range_x().contains(&uxyz.0) && self.range_y().contains(&uxyz.1));
+        match &self.chunks[uxyz.2 / CHUNK] {
+            Some(chunk) => chunk[offset(uxyz)],
+            None => T::default(),
+        }
+    }
+    // returns the previous value
+    pub fn set(&mut self, uxyz: (usize, usize, usize), value: T) -> T {
+        debug_assert!(self.range_x().contains(&uxyz.0) && self.range_y().contains(&uxyz.1));
+        set(&mut self.chunks[uxyz.2 / CHUNK], offset(uxyz), value)
+    }
+    // copy cells 'ks' of the row at x = i, y = j from 'from', which has
+    // to cover the same system, the cells may not span several chunks
+    pub fn copy_row(&mut self, from: &Chunks<T>, (i, j): (usize, usize), ks: std::ops::Range<usize>)
+    where
+        T: Send + Sync,
+    {
+        debug_assert_eq!(ks.start / CHUNK, (ks.end - 1) / CHUNK);
+        let start = offset((i, j, ks.start));
+        let to = &mut self.chunks[ks.start / CHUNK];
+        match (from.chunk_of((i, j, ks.start)), to) {
+            (Some((cells, _)), Some(to)) => {
+                to[start..start + ks.len()].copy_from_slice(&cells[start..start + ks.len()])
+            }
+            (Some((cells, _)), to) => {
+                let row = &cells[start..start + ks.len()];
+                if row.iter().any(|&v| v != T::default()) {
+                    let mut chunk = allocate();
+                    chunk[start..start + ks.len()].copy_from_slice(row);
+                    *to = Some(chunk);
+                }
+            }
+            (None, Some(to)) => to[start..start + ks.len()].fill(T::default()),
+            (None, None) => {}
+        }
+    }
+    // release all chunks that only hold the default
+    pub fn trim(&mut self) {
+        trim(self.chunks);
+    }
+}
@@ -1,5 +1,13 @@
-use bevy::prelude::Resource;
-use clap::{Parser, ValueEnum};
+use crate::boundary::Boundaries;
+use crate::gradient::{adjustable_bw, adjustable_spectrum, petrol};
+use crate::rules::{Preset, Rules, Semantics};
+use crate::species::{Competition, Neighbours, Ownership, MAX_SPECIES};
+use crate::system::{Scheme, Stepping, SystemDims};
+use bevy::prelude::{ClearColor, Color, Resource};
+use clap::{
+    error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueEnum,
+};
+use colorgrad::LinearGradient;
 
 /// Bevy app for 3D cellular automata with command line interface.
 #[derive(Clone, Debug, Parser, Resource)]
@@ -13,10 +21,16 @@ pub struct Cli {
     #[arg(short, long, default_value_t = 111222333444555)]
     pub seed: u64,
 
-    /// Edge length (defaults to 64 i.e. 64x64x64 cubes, min 16 max 96)
+    /// Edge length (defaults to 64 i.e. 64x64x64 cubes, min 16 max 1024)
     #[arg(short, long, default_value_t = 64usize)]
     pub edge_length: usize,
 
+    /// Non-cubic system, e.g. 128x128x8, replaces the edge length (min 1
+    /// max 1024 per axis, unlike the edge length there is no minimum of 16,
+    /// so thin slabs and rods are possible)
+    #[arg(long, conflicts_with = "edge_length")]
+    pub dims: Option<SystemDims>,
+
     /// Minimum density (min 0.0, max 1.0)
     #[arg(long, default_value_t = 0.025)]
     pub minimum: f64,
@@ -43,7 +57,38 @@ pub struct Cli {
 
     /// Rules
     #[arg(short, long, default_value = "6-8/7/4/M")]
-    pub rules: String,
+    pub rules: Rules,
+
+    /// Named preset of rules, densities, colors and light, options given
+    /// explicitly take precedence (see --list-presets)
+    #[arg(short, long, value_parser = parse_preset)]
+    pub preset: Option<&'static Preset>,
+
+    /// List all presets and exit
+    #[arg(long, default_value_t = false)]
+    pub list_presets: bool,
+
+    /// How the rules are interpreted
+    #[arg(long, default_value = "legacy")]
+    pub semantics: Semantics,
+
+    /// Life regained per step by weakened cubes within the survival range, 0 disables healing
+    #[arg(long, default_value_t = 0)]
+    pub heal: isize,
+
+    /// Rules of a competing species, given once per species, e.g.
+    /// --species 4/4/5/M --species 6-8/7/4/M, every species is seeded in its
+    /// own slab along the x axis and colored with its own hue
+    #[arg(long, value_parser = parse_species, conflicts_with_all = ["rules", "update", "unbounded"])]
+    pub species: Vec<Rules>,
+
+    /// Neighbours counted by the rules of competing species
+    #[arg(long, default_value = "own")]
+    pub neighbours: Neighbours,
+
+    /// Species of a new cube if several competing species could spawn it
+    #[arg(long, default_value = "majority")]
+    pub ownership: Ownership,
 
     /// Core size
     #[arg(short, long = "divisor", default_value_t = 10)]
@@ -52,6 +97,114 @@ pub struct Cli {
     /// Core density
     #[arg(long, default_value_t = 0.75)]
     pub core_density: f64,
+
+    /// Stepping strategy, all of them produce identical results
+    #[arg(long, default_value = "bitwise")]
+    pub stepping: Stepping,
+
+    /// Update scheme: synchronous, sequential (random order), asynchronous[=chance]
+    /// (each cell updates with the given chance, 0.5 by default), checkerboard or
+    /// second-order (reversible, see the 't' key)
+    #[arg(long, default_value = "synchronous")]
+    pub update: Scheme,
+
+    /// Boundary of each axis: wrap, dead, alive, mirror, twisted or shifted,
+    /// e.g. x=wrap,y=dead,z=mirror (a single mode applies to all axes)
+    #[arg(short, long, default_value = "wrap")]
+    pub boundary: Boundaries,
+
+    /// Unbounded system, cubes are no longer wrapped around at the walls
+    #[arg(long, default_value_t = false)]
+    pub unbounded: bool,
+
+    /// Jump ahead by the given number of generations right after the initial fill,
+    /// using HashLife in an unbounded system
+    #[arg(long, default_value_t = 0)]
+    pub advance: u64,
+
+    /// Maximum number of rendered cubes, any further cubes are simulated but not shown
+    #[arg(long, default_value_t = 500_000)]
+    pub max_cubes: usize,
+}
+
+impl Cli {
+    // parse the command line, the settings of a preset replace the
+    // default values of the options that have not been given explicitly
+    pub fn parse_with_preset() -> Self {
+        let matches = Self::command().get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if cli.species.len() > MAX_SPECIES {
+            Self::command()
+                .error(
+                    ErrorKind::TooManyValues,
+                    format!("at most {MAX_SPECIES} species can compete"),
+                )
+                .exit();
+        }
+        if let Some(preset) = cli.preset {
+            cli.apply_preset(preset, |id| {
+                matches.value_source(id) != Some(ValueSource::CommandLine)
+            });
+        }
+        cli
+    }
+    // replace the settings for which 'replace' returns true by the
+    // settings of the preset, e.g. when cycling through presets in game
+    pub fn apply_preset(&mut self, preset: &'static Preset, replace: impl Fn(&str) -> bool) {
+        self.preset = Some(preset);
+        if replace("rules") {
+            self.rules = preset.rules();
+        }
+        if replace("semantics") {
+            self.semantics = preset.semantics;
+        }
+        if replace("density") {
+            self.density = preset.density;
+        }
+        if replace("minimum") {
+            self.minimum = preset.minimum;
+        }
+        if replace("maximum") {
+            self.maximum = preset.maximum;
+        }
+        if replace("color_gradient") {
+            self.color_gradient =
+                ColorGradient::from_str(preset.color, true).expect("invalid preset color");
+        }
+        if replace("light_mode") {
+            self.light_mode =
+                LightMode::from_str(preset.light, true).expect("invalid preset light mode");
+        }
+    }
+    // the rules as specified by all options
+    pub fn rules(&self) -> Rules {
+        let mut rules = match self.species.is_empty() {
+            true => self.rules.clone(),
+            false => Rules::competing(Competition::new(
+                self.species.clone(),
+                self.neighbours,
+                self.ownership,
+            )),
+        };
+        rules.set_semantics(self.semantics);
+        rules.set_heal(self.heal);
+        rules
+    }
+}
+
+fn parse_preset(name: &str) -> Result<&'static Preset, String> {
+    Preset::find(name).ok_or_else(|| {
+        format!("unknown preset '{name}', see --list-presets for all available presets")
+    })
+}
+
+// rules of a competing species, which have to count their neighbours
+fn parse_species(input: &str) -> Result<Rules, String> {
+    let rules = Rules::parse_from_str(input).map_err(|e| e.to_string())?;
+    if rules.table().is_some() || rules.blocks().is_some() {
+        return Err("competing species can not follow tables or block rules".to_string());
+    }
+    Ok(rules)
 }
 
 #[derive(ValueEnum, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -66,6 +219,16 @@ pub enum ColorGradient {
     Petrol,
 }
 
+impl ColorGradient {
+    pub fn gradient(&self) -> LinearGradient {
+        match self {
+            ColorGradient::Rainbow => adjustable_spectrum(0.2, 0.8),
+            ColorGradient::BlackWhite => adjustable_bw(0.1, 0.9),
+            ColorGradient::Petrol => petrol(1.0),
+        }
+    }
+}
+
 #[derive(ValueEnum, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub enum LightMode {
     /// normal light
@@ -74,3 +237,40 @@ pub enum LightMode {
     /// bloom effect
     Bloom,
 }
+
+impl LightMode {
+    // illuminance of the directional light and brightness of the ambient
+    // light
+    pub fn intensity(&self) -> (f32, f32) {
+        match self {
+            LightMode::Bloom => (1000.0, 250.0),
+            LightMode::Normal => (2000.0, 500.0),
+        }
+    }
+    pub fn clear_color(&self) -> ClearColor {
+        match self {
+            LightMode::Bloom => ClearColor(Color::srgb(0.15, 0.15, 0.15)),
+            LightMode::Normal => ClearColor::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::PRESETS;
+
+    #[test]
+    fn presets() {
+        for preset in PRESETS {
+            let name = preset.name;
+            assert!(Rules::parse_from_str(preset.rules).is_ok(), "{name}");
+            assert!(
+                ColorGradient::from_str(preset.color, true).is_ok(),
+                "{name}"
+            );
+            assert!(LightMode::from_str(preset.light, true).is_ok(), "{name}");
+            assert_eq!(Preset::find(name), Some(preset));
+        }
+    }
+}
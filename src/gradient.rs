@@ -1,8 +1,8 @@
 use colorgrad::{Color, GradientBuilder, LinearGradient};
 
 pub fn adjustable_spectrum(factor: f32, opposite: f32) -> LinearGradient {
-    let factor_clamp = factor.max(0.0).min(1.0);
-    let oppo_clamp = opposite.max(0.0).min(1.0);
+    let factor_clamp = factor.clamp(0.0, 1.0);
+    let oppo_clamp = opposite.clamp(0.0, 1.0);
     GradientBuilder::new()
         .colors(&[
             Color::new(factor_clamp, oppo_clamp, oppo_clamp, 1.0),
@@ -17,8 +17,8 @@ pub fn adjustable_spectrum(factor: f32, opposite: f32) -> LinearGradient {
 }
 
 pub fn adjustable_bw(start: f32, end: f32) -> LinearGradient {
-    let sc = start.max(0.0).min(1.0);
-    let ec = end.max(0.0).min(1.0);
+    let sc = start.clamp(0.0, 1.0);
+    let ec = end.clamp(0.0, 1.0);
     GradientBuilder::new()
         .colors(&[Color::new(sc, sc, sc, 1.0), Color::new(ec, ec, ec, 1.0)])
         .build::<LinearGradient>()
@@ -26,7 +26,7 @@ pub fn adjustable_bw(start: f32, end: f32) -> LinearGradient {
 }
 
 pub fn petrol(factor: f32) -> LinearGradient {
-    let fc = factor.max(0.0).min(1.0);
+    let fc = factor.clamp(0.0, 1.0);
     GradientBuilder::new()
         .colors(&[
             Color::new(0.0 * fc, 0.0 * fc, 0.4 * fc, 1.0),
@@ -36,3 +36,24 @@ pub fn petrol(factor: f32) -> LinearGradient {
         .build::<LinearGradient>()
         .unwrap()
 }
+
+// gradient of a competing species, every species has its own hue, which
+// varies between a darker and a lighter shade
+pub fn species_gradient(species: u8) -> LinearGradient {
+    const HUES: [(f32, f32, f32); 6] = [
+        (0.9, 0.2, 0.1),
+        (0.1, 0.4, 0.9),
+        (0.2, 0.8, 0.2),
+        (0.9, 0.8, 0.1),
+        (0.7, 0.2, 0.9),
+        (0.1, 0.8, 0.8),
+    ];
+    let (r, g, b) = HUES[species as usize % HUES.len()];
+    GradientBuilder::new()
+        .colors(&[
+            Color::new(r * 0.6, g * 0.6, b * 0.6, 1.0),
+            Color::new(r, g, b, 1.0),
+        ])
+        .build::<LinearGradient>()
+        .unwrap()
+}
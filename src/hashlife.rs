@@ -0,0 +1,631 @@
+use crate::rules::Rules;
+use crate::sparse::{Position, SparseSystem3d};
+use crate::system::{
+    automaton_from_state, next_state, state_from_automaton, AutoSystem3d, Automaton, Lookup,
+    Occupancy, SystemDims,
+};
+use std::collections::HashMap;
+
+// identifies a canonical node, see 'HashLife'
+type NodeId = u32;
+
+// a node of level 0 is a single cell, a node of level k is a cube of
+// 2^k x 2^k x 2^k cells made up of eight nodes of level k - 1
+// children are ordered like cells in 'AutoSystem3d', i.e. child
+// (x << 2) | (y << 1) | z, where 1 means the upper half along an axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Leaf(u8),
+    Inner { level: u8, children: [NodeId; 8] },
+}
+
+// once the arena holds more nodes than this, unreachable nodes and all
+// memoised results are dropped between two jumps
+const GC_THRESHOLD: usize = 1 << 22;
+
+// HashLife engine, i.e. a memoised octree of canonical nodes
+// every distinct cube of cells is stored only once, and the evolution
+// of each node is cached, so recurring structures (still lifes,
+// oscillators, spaceships, empty space) are evaluated only once no
+// matter where and when they occur
+// this makes it possible to jump ahead by 2^k generations at once
+// just like 'SparseSystem3d' the universe is unbounded, empty cells
+// without any neighbours never spawn, and growth is always enabled
+// chance can not be memoised, stochastic rules are evaluated as if every
+// possible outcome occurred, see 'Rules::is_stochastic'
+#[derive(Debug, Clone)]
+pub struct HashLife {
+    rules: Rules,
+    lookup: Lookup,
+    occupancy: Occupancy,
+    offsets: Vec<(isize, isize, isize)>,
+    weights: Vec<usize>,
+    // level of the smallest nodes that are stepped directly, the margin
+    // around their center has to cover the reach of the neighbourhood
+    base: u8,
+    nodes: Vec<Node>,
+    populations: Vec<usize>,
+    index: HashMap<Node, NodeId>,
+    results: HashMap<(NodeId, u8), NodeId>,
+    empty: Vec<NodeId>,
+    // the root of level k covers -2^(k-1)..2^(k-1) along every axis
+    root: NodeId,
+    generation: u64,
+}
+
+impl HashLife {
+    pub fn new(rules: &Rules) -> Self {
+        let neighbourhood = rules.counting();
+        let reach = neighbourhood.reach().next_power_of_two();
+        let mut hashlife = Self {
+            rules: rules.clone(),
+            lookup: Lookup::new(rules, neighbourhood.max_count()),
+            occupancy: Occupancy::new(rules),
+            offsets: neighbourhood.offsets(),
+            weights: neighbourhood.weights(),
+            base: 2 + reach.trailing_zeros() as u8,
+            nodes: Vec::new(),
+            populations: Vec::new(),
+            index: HashMap::new(),
+            results: HashMap::new(),
+            empty: Vec::new(),
+            root: 0,
+            generation: 0,
+        };
+        hashlife.root = hashlife.empty(3);
+        hashlife
+    }
+    // copy all cubes of a bounded system, cell (0, 0, 0) keeps its position
+    pub fn from_system(rules: &Rules, system: &AutoSystem3d) -> Self {
+        let mut hashlife = Self::new(rules);
+        let dims = system.dims();
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    if let Some(at) = system.get_at_xyz((i, j, k)) {
+                        hashlife.set_xyz((i as isize, j as isize, k as isize), at);
+                    }
+                }
+            }
+        }
+        hashlife
+    }
+    pub fn from_sparse(rules: &Rules, system: &SparseSystem3d) -> Self {
+        let mut hashlife = Self::new(rules);
+        for (ixyz, at) in system.cells() {
+            hashlife.set_xyz(ixyz, at);
+        }
+        hashlife
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    pub fn population(&self) -> usize {
+        self.populations[self.root as usize]
+    }
+    // number of distinct nodes currently held in memory
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+    fn level(&self, id: NodeId) -> u8 {
+        match self.nodes[id as usize] {
+            Node::Leaf(_) => 0,
+            Node::Inner { level, .. } => level,
+        }
+    }
+    fn children(&self, id: NodeId) -> [NodeId; 8] {
+        match self.nodes[id as usize] {
+            Node::Leaf(_) => panic!("a single cell has no children"),
+            Node::Inner { children, .. } => children,
+        }
+    }
+    // canonical id of a node
+    fn intern(&mut self, node: Node) -> NodeId {
+        if let Some(&id) = self.index.get(&node) {
+            return id;
+        }
+        let population = match node {
+            Node::Leaf(state) => (state != 0) as usize,
+            Node::Inner { children, .. } => {
+                children.iter().map(|&c| self.populations[c as usize]).sum()
+            }
+        };
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        self.populations.push(population);
+        self.index.insert(node, id);
+        id
+    }
+    fn leaf(&mut self, state: u8) -> NodeId {
+        self.intern(Node::Leaf(state))
+    }
+    fn join(&mut self, children: [NodeId; 8]) -> NodeId {
+        let level = self.level(children[0]) + 1;
+        self.intern(Node::Inner { level, children })
+    }
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let id = match self.empty.last() {
+                None => self.leaf(0),
+                Some(&below) => self.join([below; 8]),
+            };
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+    // the 4 x 4 x 4 nodes two levels below
+    fn grandchildren(&self, id: NodeId) -> [[[NodeId; 4]; 4]; 4] {
+        let mut grid = [[[0; 4]; 4]; 4];
+        for (c, &child) in self.children(id).iter().enumerate() {
+            for (g, &grandchild) in self.children(child).iter().enumerate() {
+                let x = (c >> 2) * 2 + (g >> 2);
+                let y = (c >> 1 & 1) * 2 + (g >> 1 & 1);
+                let z = (c & 1) * 2 + (g & 1);
+                grid[x][y][z] = grandchild;
+            }
+        }
+        grid
+    }
+    // node made up of the 2 x 2 x 2 nodes of a grid starting at (a, b, c)
+    fn join_at<const N: usize>(
+        &mut self,
+        grid: &[[[NodeId; N]; N]; N],
+        a: usize,
+        b: usize,
+        c: usize,
+    ) -> NodeId {
+        let mut children = [0; 8];
+        for (i, child) in children.iter_mut().enumerate() {
+            *child = grid[a + (i >> 2)][b + (i >> 1 & 1)][c + (i & 1)];
+        }
+        self.join(children)
+    }
+    // the cube of half the edge length in the center of a node
+    fn center(&mut self, id: NodeId) -> NodeId {
+        let grid = self.grandchildren(id);
+        self.join_at(&grid, 1, 1, 1)
+    }
+    // same cells, surrounded by empty space, one level higher
+    fn expand(&mut self, id: NodeId) -> NodeId {
+        let level = self.level(id);
+        let empty = self.empty(level - 1);
+        let children = self.children(id);
+        let mut outer = [0; 8];
+        for (i, child) in outer.iter_mut().enumerate() {
+            let mut inner = [empty; 8];
+            // the corner that touches the center
+            inner[7 - i] = children[i];
+            *child = self.join(inner);
+        }
+        self.join(outer)
+    }
+    // true if every cube lies within the center of the node
+    fn is_centered(&self, id: NodeId) -> bool {
+        let grid = self.grandchildren(id);
+        (0..64).all(|i| {
+            let (x, y, z) = (i >> 4, i >> 2 & 3, i & 3);
+            let inner = (1..3).contains(&x) && (1..3).contains(&y) && (1..3).contains(&z);
+            inner || self.populations[grid[x][y][z] as usize] == 0
+        })
+    }
+    // one generation of the center cells of a node of the base level
+    fn base_step(&mut self, id: NodeId) -> NodeId {
+        let side = 1usize << self.base;
+        let mut cells = vec![0u8; side * side * side];
+        self.write_cells(id, (0, 0, 0), side, &mut cells);
+        let (quarter, half) = (side / 4, side / 2);
+        let mut next = vec![0u8; half * half * half];
+        for (i, cell) in next.iter_mut().enumerate() {
+            let x = i / (half * half) + quarter;
+            let y = i / half % half + quarter;
+            let z = i % half + quarter;
+            let n: usize = self
+                .offsets
+                .iter()
+                .zip(&self.weights)
+                .filter(|(o, _)| {
+                    let p = (x as isize + o.0, y as isize + o.1, z as isize + o.2);
+                    let p = (p.0 as usize, p.1 as usize, p.2 as usize);
+                    self.occupancy
+                        .counts(cells[(p.0 * side + p.1) * side + p.2])
+                })
+                .map(|(_, w)| w)
+                .sum();
+            // empty space far away from any cube stays empty
+            let spawn = self.lookup.spawn[n] && n > 0;
+            let state = cells[(x * side + y) * side + z];
+            *cell = next_state(state, self.lookup.survive[n], spawn, &self.rules);
+        }
+        self.build(&next, half, (0, 0, 0), half)
+    }
+    // copy the cells below 'id', whose first cell is at 'origin', into a
+    // cube of 'side' x 'side' x 'side' cells
+    fn write_cells(
+        &self,
+        id: NodeId,
+        origin: (usize, usize, usize),
+        side: usize,
+        cells: &mut [u8],
+    ) {
+        match self.nodes[id as usize] {
+            Node::Leaf(state) => cells[(origin.0 * side + origin.1) * side + origin.2] = state,
+            Node::Inner { level, children } => {
+                if self.populations[id as usize] == 0 {
+                    return;
+                }
+                let half = 1usize << (level - 1);
+                for (c, &child) in children.iter().enumerate() {
+                    let o = (
+                        origin.0 + (c >> 2) * half,
+                        origin.1 + (c >> 1 & 1) * half,
+                        origin.2 + (c & 1) * half,
+                    );
+                    self.write_cells(child, o, side, cells);
+                }
+            }
+        }
+    }
+    // the node of 'size' x 'size' x 'size' cells of a cube of 'side' x
+    // 'side' x 'side' cells, whose first cell is at 'origin'
+    fn build(
+        &mut self,
+        cells: &[u8],
+        side: usize,
+        origin: (usize, usize, usize),
+        size: usize,
+    ) -> NodeId {
+        if size == 1 {
+            return self.leaf(cells[(origin.0 * side + origin.1) * side + origin.2]);
+        }
+        let half = size / 2;
+        let mut children = [0; 8];
+        for (c, child) in children.iter_mut().enumerate() {
+            let o = (
+                origin.0 + (c >> 2) * half,
+                origin.1 + (c >> 1 & 1) * half,
+                origin.2 + (c & 1) * half,
+            );
+            *child = self.build(cells, side, o, half);
+        }
+        self.join(children)
+    }
+    // the center of a node of level k after 2^j generations, where
+    // j <= k - base
+    fn result(&mut self, id: NodeId, j: u8) -> NodeId {
+        if let Some(&r) = self.results.get(&(id, j)) {
+            return r;
+        }
+        let level = self.level(id);
+        let r = if self.populations[id as usize] == 0 {
+            self.empty(level - 1)
+        } else if level == self.base {
+            self.base_step(id)
+        } else {
+            // at full speed both halves advance by 2^(j - 1) generations,
+            // otherwise only the second half advances
+            let full = j == level - self.base;
+            let half = if full { j - 1 } else { j };
+            let grid = self.grandchildren(id);
+            let mut first = [[[0; 3]; 3]; 3];
+            for i in 0..27 {
+                let (a, b, c) = (i / 9, i / 3 % 3, i % 3);
+                let sub = self.join_at(&grid, a, b, c);
+                first[a][b][c] = if full {
+                    self.result(sub, half)
+                } else {
+                    self.center(sub)
+                };
+            }
+            let mut second = [0; 8];
+            for (i, node) in second.iter_mut().enumerate() {
+                let sub = self.join_at(&first, i >> 2, i >> 1 & 1, i & 1);
+                *node = self.result(sub, half);
+            }
+            self.join(second)
+        };
+        self.results.insert((id, j), r);
+        r
+    }
+    // replace a single cell, below 'id' of level 'level' whose first
+    // cell is at 'origin'
+    fn set_in(&mut self, id: NodeId, origin: Position, ixyz: Position, state: u8) -> NodeId {
+        let level = self.level(id);
+        if level == 0 {
+            return self.leaf(state);
+        }
+        let half = 1isize << (level - 1);
+        let upper = (
+            (ixyz.0 >= origin.0 + half) as usize,
+            (ixyz.1 >= origin.1 + half) as usize,
+            (ixyz.2 >= origin.2 + half) as usize,
+        );
+        let i = upper.0 << 2 | upper.1 << 1 | upper.2;
+        let child_origin = (
+            origin.0 + upper.0 as isize * half,
+            origin.1 + upper.1 as isize * half,
+            origin.2 + upper.2 as isize * half,
+        );
+        let mut children = self.children(id);
+        children[i] = self.set_in(children[i], child_origin, ixyz, state);
+        self.join(children)
+    }
+    fn root_origin(&self) -> Position {
+        let half = -(1isize << (self.level(self.root) - 1));
+        (half, half, half)
+    }
+    fn contains(&self, ixyz: Position) -> bool {
+        let half = 1isize << (self.level(self.root) - 1);
+        [ixyz.0, ixyz.1, ixyz.2]
+            .iter()
+            .all(|c| (-half..half).contains(c))
+    }
+    pub fn access_xyz(&mut self, ixyz: Position, value: Option<Automaton>) {
+        while !self.contains(ixyz) {
+            self.root = self.expand(self.root);
+        }
+        let origin = self.root_origin();
+        self.root = self.set_in(self.root, origin, ixyz, state_from_automaton(value));
+    }
+    pub fn set_xyz(&mut self, ixyz: Position, value: Automaton) {
+        self.access_xyz(ixyz, Some(value));
+    }
+    pub fn delete_xyz(&mut self, ixyz: Position) {
+        self.access_xyz(ixyz, None);
+    }
+    // advance the universe by 2^j generations
+    fn jump(&mut self, j: u8) {
+        // the pattern has to lie within the center of the center, so it
+        // can not grow beyond the center during the jump
+        while self.level(self.root) < j + self.base + 1 || !self.is_centered(self.root) {
+            self.root = self.expand(self.root);
+        }
+        self.root = self.expand(self.root);
+        self.root = self.result(self.root, j);
+        // keep the root small
+        while self.level(self.root) > 3 && self.is_centered(self.root) {
+            self.root = self.center(self.root);
+        }
+        self.generation += 1 << j;
+        if self.nodes.len() > GC_THRESHOLD {
+            self.collect_garbage();
+        }
+    }
+    // advance the universe by an arbitrary number of generations
+    pub fn advance(&mut self, generations: u64) {
+        for j in 0..u64::BITS as u8 {
+            if generations >> j & 1 == 1 {
+                self.jump(j);
+            }
+        }
+    }
+    // drop all nodes that are not part of the current universe
+    fn collect_garbage(&mut self) {
+        let mut fresh = Self::new(&self.rules);
+        let mut copied = HashMap::new();
+        fresh.root = fresh.copy_from(self, self.root, &mut copied);
+        fresh.generation = self.generation;
+        *self = fresh;
+    }
+    fn copy_from(
+        &mut self,
+        other: &Self,
+        id: NodeId,
+        copied: &mut HashMap<NodeId, NodeId>,
+    ) -> NodeId {
+        if let Some(&new) = copied.get(&id) {
+            return new;
+        }
+        let new = match other.nodes[id as usize] {
+            Node::Leaf(state) => self.leaf(state),
+            Node::Inner { children, .. } => {
+                let children = children.map(|c| self.copy_from(other, c, copied));
+                self.join(children)
+            }
+        };
+        copied.insert(id, new);
+        new
+    }
+    // all cubes of the universe
+    pub fn cells(&self) -> Vec<(Position, Automaton)> {
+        let mut cells = Vec::with_capacity(self.population());
+        self.collect_cells(self.root, self.root_origin(), &mut cells);
+        cells
+    }
+    fn collect_cells(&self, id: NodeId, origin: Position, cells: &mut Vec<(Position, Automaton)>) {
+        if self.populations[id as usize] == 0 {
+            return;
+        }
+        match self.nodes[id as usize] {
+            Node::Leaf(state) => {
+                if let Some(at) = automaton_from_state(state) {
+                    cells.push((origin, at));
+                }
+            }
+            Node::Inner { level, children } => {
+                let half = 1isize << (level - 1);
+                for (i, &child) in children.iter().enumerate() {
+                    let child_origin = (
+                        origin.0 + (i >> 2) as isize * half,
+                        origin.1 + (i >> 1 & 1) as isize * half,
+                        origin.2 + (i & 1) as isize * half,
+                    );
+                    self.collect_cells(child, child_origin, cells);
+                }
+            }
+        }
+    }
+    pub fn to_sparse(&self) -> SparseSystem3d {
+        let mut sparse = SparseSystem3d::new();
+        for (ixyz, at) in self.cells() {
+            sparse.set_xyz(ixyz, at);
+        }
+        sparse
+    }
+    // load the universe into a bounded system for viewing
+    // if the cubes do not fit into the system at their current position,
+    // they are moved to the center, anything that does not fit at all
+    // wraps around at the walls
+    pub fn to_system(&self, dims: &SystemDims) -> AutoSystem3d {
+        let mut system = AutoSystem3d::new_from_dims(dims);
+        let cells = self.cells();
+        let size = (dims.x() as isize, dims.y() as isize, dims.z() as isize);
+        let fits = cells.iter().all(|(p, _)| {
+            (0..size.0).contains(&p.0) && (0..size.1).contains(&p.1) && (0..size.2).contains(&p.2)
+        });
+        let shift = match cells
+            .iter()
+            .map(|c| c.0)
+            .reduce(|lo, p| (lo.0.min(p.0), lo.1.min(p.1), lo.2.min(p.2)))
+        {
+            Some(lo) if !fits => {
+                let hi = cells
+                    .iter()
+                    .map(|c| c.0)
+                    .fold(lo, |hi, p| (hi.0.max(p.0), hi.1.max(p.1), hi.2.max(p.2)));
+                (
+                    size.0 / 2 - (lo.0 + hi.0).div_euclid(2),
+                    size.1 / 2 - (lo.1 + hi.1).div_euclid(2),
+                    size.2 / 2 - (lo.2 + hi.2).div_euclid(2),
+                )
+            }
+            _ => (0, 0, 0),
+        };
+        for (p, at) in cells {
+            let uxyz = (
+                (p.0 + shift.0).rem_euclid(size.0) as usize,
+                (p.1 + shift.1).rem_euclid(size.1) as usize,
+                (p.2 + shift.2).rem_euclid(size.2) as usize,
+            );
+            system.set_xyz(uxyz, at);
+        }
+        system
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::{Boundaries, Boundary};
+    use crate::system::Noise;
+    use rand::prelude::*;
+    use xorwowgen::xorwow64::XorA;
+
+    // a pseudorandom cube of 8 x 8 x 8 cells in the middle of a 64 x 64 x 64
+    // system, under these rules it grows slowly enough to stay away from
+    // the walls for more than 70 generations
+    fn seeded() -> (Rules, SystemDims, AutoSystem3d) {
+        let rules = Rules::parse_from_str("6-8/7/4/M").unwrap();
+        let dims = SystemDims::new(64, 64, 64);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.set_boundaries(Boundaries::all(Boundary::Dead));
+        let mut rng = XorA::seed_from_u64(5);
+        for i in 28..36 {
+            for j in 28..36 {
+                for k in 28..36 {
+                    if rng.gen_bool(0.4) {
+                        system.set_xyz((i, j, k), Automaton::new(rules.life()));
+                    }
+                }
+            }
+        }
+        (rules, dims, system)
+    }
+
+    fn sorted(mut cells: Vec<(Position, Automaton)>) -> Vec<(Position, Automaton)> {
+        cells.sort_by_key(|c| c.0);
+        cells
+    }
+
+    // the cubes of a bounded system, which must not touch the walls
+    fn system_cells(system: &AutoSystem3d) -> Vec<(Position, Automaton)> {
+        let dims = system.dims();
+        let mut cells = Vec::new();
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    if let Some(at) = system.get_at_xyz((i, j, k)) {
+                        let ixyz = (i as isize, j as isize, k as isize);
+                        let edge = dims.x() as isize - 1;
+                        assert!([ixyz.0, ixyz.1, ixyz.2]
+                            .iter()
+                            .all(|c| (1..edge).contains(c)));
+                        cells.push((ixyz, at));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn advance_matches_stepping() {
+        let (rules, dims, start) = seeded();
+        let mut system = start.clone();
+        let mut generation = 0;
+        for generations in [1, 2, 5, 13, 70] {
+            while generation < generations {
+                system.step(&rules, &dims, true, Noise::new(0, generation));
+                generation += 1;
+            }
+            let mut hashlife = HashLife::from_system(&rules, &start);
+            hashlife.advance(generations);
+            assert_eq!(hashlife.generation(), generations);
+            assert_eq!(hashlife.population(), system.population());
+            assert_eq!(
+                sorted(hashlife.cells()),
+                system_cells(&system),
+                "{generations} generations"
+            );
+        }
+    }
+
+    #[test]
+    fn jump_matches_stepping() {
+        let (rules, dims, mut system) = seeded();
+        let mut hashlife = HashLife::from_system(&rules, &system);
+        let mut generation = 0;
+        // jumps of 1, 2, 4, 8 and 16 generations, one after the other
+        for j in 0..5 {
+            hashlife.jump(j);
+            for _ in 0..1u64 << j {
+                system.step(&rules, &dims, true, Noise::new(0, generation));
+                generation += 1;
+            }
+            assert_eq!(hashlife.generation(), generation);
+            assert_eq!(sorted(hashlife.cells()), system_cells(&system), "jump {j}");
+        }
+    }
+
+    #[test]
+    fn memoised_results_are_stable() {
+        let (rules, _, system) = seeded();
+        let mut once = HashLife::from_system(&rules, &system);
+        once.advance(40);
+        let expected = sorted(once.cells());
+        // the same jumps again, with all results already memoised
+        let start = HashLife::from_system(&rules, &system);
+        let mut cached = once.clone();
+        cached.root = cached.copy_from(&start, start.root, &mut HashMap::new());
+        cached.generation = 0;
+        let results = cached.results.len();
+        cached.advance(40);
+        assert_eq!(cached.results.len(), results);
+        assert_eq!(sorted(cached.cells()), expected);
+        // after dropping unreachable nodes and memoised results on the way
+        let mut collected = HashLife::from_system(&rules, &system);
+        collected.advance(15);
+        let nodes = collected.node_count();
+        collected.collect_garbage();
+        assert!(collected.node_count() < nodes);
+        assert!(collected.results.is_empty());
+        assert_eq!(collected.generation(), 15);
+        collected.advance(25);
+        assert_eq!(sorted(collected.cells()), expected);
+        // stepping one generation at a time reuses the base steps
+        let mut single = HashLife::from_system(&rules, &system);
+        for _ in 0..40 {
+            single.advance(1);
+        }
+        assert_eq!(sorted(single.cells()), expected);
+    }
+}
@@ -19,6 +19,9 @@ pub fn show_helptext(
                     s: decrease update speed\n\
                     n: spawn new cubes\n\
                     m: spawn new cubes in specified center area\n\
+                    f: focus camera on the cubes\n\
+                    p: switch to the next preset\n\
+                    t: reverse the direction of time (second-order only)\n\
                     h: toggle help text\n\
                     press 'space' to pause the system\n\n\
                     press 'q' or 'esc' to quit"),
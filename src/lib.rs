@@ -1,15 +1,30 @@
+#[cfg(feature = "render")]
 use bevy::prelude::{Resource, Timer, TimerMode};
 use colorgrad::LinearGradient;
+#[cfg(feature = "render")]
 use std::time::Duration;
 
+pub mod bitgrid;
+pub mod blocks;
+pub mod boundary;
+pub mod chunks;
+#[cfg(feature = "render")]
 pub mod cli;
 pub mod gradient;
+pub mod hashlife;
+#[cfg(feature = "render")]
 pub mod helptext;
+pub mod margolus;
+pub mod mask;
 pub mod rules;
+pub mod sparse;
+pub mod species;
 pub mod system;
+pub mod table;
+#[cfg(feature = "render")]
 pub mod update;
 
-use crate::system::SystemDims;
+use crate::system::{Noise, SystemDims};
 
 // set emission intensity
 pub const BLOOM: f32 = 0.8;
@@ -20,8 +35,15 @@ const FIELD_UNIT: f32 = 0.15625; // 64 x 64 x 64 blocks == 10.0 x 10.0 x 10.0 si
 const HALF_UNIT: f32 = FIELD_UNIT / 2.0;
 // leave a small space between each cube
 pub const CUBE_SIZE: f32 = FIELD_UNIT * 0.9;
+// limits of the edge length of a bounded system
+pub const MIN_EDGE: usize = 16;
+pub const MAX_EDGE: usize = 1024;
+// warn if a system may take more memory than this
+pub const MEMORY_WARNING: usize = 1 << 30;
 // convert system coordinates to world coordinates
-pub fn calc_spawn_coords(xyz: (usize, usize, usize), dims: &SystemDims) -> (f32, f32, f32) {
+// the box spanned by 'dims' is centered around the origin, coordinates
+// outside of it (e.g. in an unbounded system) are fine as well
+pub fn calc_spawn_coords(xyz: (isize, isize, isize), dims: &SystemDims) -> (f32, f32, f32) {
     let half_x = (dims.x() as f32 * FIELD_UNIT) / 2.0;
     let half_y = (dims.y() as f32 * FIELD_UNIT) / 2.0;
     let half_z = (dims.z() as f32 * FIELD_UNIT) / 2.0;
@@ -36,12 +58,14 @@ pub fn isizify3(i: usize, j: usize, k: usize) -> (isize, isize, isize) {
     (i as isize, j as isize, k as isize)
 }
 
+#[cfg(feature = "render")]
 #[derive(Resource)]
 pub struct SystemTimer {
     pub timer: Timer,
     pub stopped: bool,
 }
 
+#[cfg(feature = "render")]
 impl SystemTimer {
     pub fn millis(duration: u64) -> Self {
         Self {
@@ -65,9 +89,12 @@ impl SystemTimer {
 }
 
 // contains data that are not to be changed once initialized
-#[derive(Resource, Clone)]
+#[derive(Clone)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub struct GlobalStatic {
     gradient: LinearGradient,
+    // one gradient per competing species, if any
+    species_gradients: Vec<LinearGradient>,
     dims: SystemDims,
     minimum: isize,
     maximum: isize,
@@ -79,6 +106,7 @@ impl GlobalStatic {
     pub fn new(gradient: LinearGradient, dims: SystemDims, minimum: isize, maximum: isize) -> Self {
         Self {
             gradient,
+            species_gradients: Vec::new(),
             dims,
             minimum,
             maximum,
@@ -92,24 +120,50 @@ impl GlobalStatic {
     pub fn dims(&self) -> SystemDims {
         self.dims
     }
+    // color every species with its own gradient instead, see
+    // 'Competition'
+    pub fn with_species(mut self, species: usize) -> Self {
+        self.species_gradients = (0..species)
+            .map(|s| gradient::species_gradient(s as u8))
+            .collect();
+        self
+    }
     pub fn gradient(&self) -> &LinearGradient {
         &self.gradient
     }
+    // the gradient of every species, or the only gradient if there are
+    // no competing species
+    pub fn gradients(&self) -> &[LinearGradient] {
+        match self.species_gradients.is_empty() {
+            true => std::slice::from_ref(&self.gradient),
+            false => &self.species_gradients,
+        }
+    }
     pub fn minimum(&self) -> isize {
         self.minimum
     }
     pub fn maximum(&self) -> isize {
         self.maximum
     }
+    pub fn max_amount(&self) -> usize {
+        self.max_amount
+    }
+    pub fn max_amount_per_thread(&self) -> usize {
+        self.max_amount_per_thread
+    }
 }
 
 // contains dynamic data of the entire system
-#[derive(Resource)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub struct GlobalData {
     seed: u64,
     amount: isize,
     growth: bool,
     generation: usize,
+    // time runs backwards, see 'Scheme::SecondOrder'
+    backwards: bool,
+    // the direction of time is about to be reversed
+    reversal: bool,
 }
 
 impl GlobalData {
@@ -119,6 +173,8 @@ impl GlobalData {
             amount: 0,
             growth: true,
             generation: 0,
+            backwards: false,
+            reversal: false,
         }
     }
     pub fn seed(&self) -> u64 {
@@ -148,18 +204,45 @@ impl GlobalData {
     pub fn generation(&self) -> usize {
         self.generation
     }
+    // count generations backwards if time runs backwards
     pub fn advance_gen(&mut self) {
-        self.generation += 1;
+        if self.backwards {
+            self.generation = self.generation.saturating_sub(1);
+        } else {
+            self.generation += 1;
+        }
+    }
+    pub fn advance_gens(&mut self, n: usize) {
+        self.generation += n;
+    }
+    pub fn backwards(&self) -> bool {
+        self.backwards
+    }
+    // reverse the direction of time with the next update, asking twice
+    // cancels the request
+    pub fn request_reversal(&mut self) {
+        self.reversal = !self.reversal;
+    }
+    // reverse the direction of time if requested, returns whether it has
+    // been reversed
+    pub fn take_reversal(&mut self) -> bool {
+        let reversal = std::mem::take(&mut self.reversal);
+        self.backwards ^= reversal;
+        reversal
+    }
+    // random numbers of stochastic rules in the current generation
+    pub fn noise(&self) -> Noise {
+        Noise::new(self.seed, self.generation as u64)
     }
 }
 
-// calculate absolute values from given cube size and density
+// calculate absolute values from given system size and density
 // density saturates at 0.0 and 1.0
-pub fn cube_density(edge: usize, density: f64) -> isize {
-    (edge.pow(3) as f64 * density.max(0.0).min(1.0)).round() as isize
+pub fn cube_density(dims: &SystemDims, density: f64) -> isize {
+    (dims.max_amount() as f64 * density.clamp(0.0, 1.0)).round() as isize
 }
 
 // calculate relative density of cubes
-pub fn rel_density(edge: usize, count: isize) -> f64 {
-    count as f64 / (edge.pow(3) as f64)
+pub fn rel_density(dims: &SystemDims, count: isize) -> f64 {
+    count as f64 / (dims.max_amount() as f64)
 }
@@ -5,34 +5,57 @@ use bevy::{
 };
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
 use cellauto3d::{
-    cli::{Cli, ColorGradient, LightMode},
+    cli::{Cli, LightMode},
     cube_density,
-    gradient::{adjustable_bw, adjustable_spectrum, petrol},
     helptext::show_helptext,
-    rules::Rules,
+    rules::{Rules, PRESETS},
+    sparse::SparseSystem3d,
     system::{AutoSystem3d, SystemDims},
     update::{
-        adjust_timer, manage_panorbit, quit, spawn_new_at_keystroke, spawn_pseudorandom_full,
-        update_system,
+        adjust_timer, cycle_preset, focus_camera, jump_ahead, manage_panorbit, quit, reverse_time,
+        spawn_new_at_keystroke, spawn_pseudorandom_full, update_system, CubeEntities,
     },
-    GlobalData, GlobalStatic, SystemTimer,
+    GlobalData, GlobalStatic, SystemTimer, MAX_EDGE, MEMORY_WARNING, MIN_EDGE,
 };
-use clap::Parser;
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_with_preset();
+    if cli.list_presets {
+        for preset in PRESETS {
+            println!("{preset}");
+        }
+        return;
+    }
 
-    let dims = SystemDims::new_cube_clamped(16, 96, cli.edge_length);
-    let grad = match cli.color_gradient {
-        ColorGradient::Rainbow => adjustable_spectrum(0.2, 0.8),
-        ColorGradient::BlackWhite => adjustable_bw(0.1, 0.9),
-        ColorGradient::Petrol => petrol(1.0),
+    // lengths given by '--dims' are not raised to 'MIN_EDGE', so thin
+    // slabs and rods are possible
+    let dims = match cli.dims {
+        Some(dims) => dims,
+        None => SystemDims::new_cube_clamped(MIN_EDGE, MAX_EDGE, cli.edge_length),
     };
-    let auto_system = AutoSystem3d::new_from_dims(&dims);
-    let rules = Rules::parse_from_str(&cli.rules).expect("unable to parse rules correctly");
+    let rules = cli.rules();
     eprintln!("Rules:\n{}", &rules);
-    let min = cube_density(cli.edge_length, cli.minimum);
-    let max = cube_density(cli.edge_length, cli.maximum);
+    if !cli.unbounded {
+        let bytes = AutoSystem3d::estimate_memory(&dims, &rules, cli.stepping, cli.update);
+        if bytes > MEMORY_WARNING {
+            eprintln!(
+                "warning: a {}x{}x{} system may need up to {} MiB of memory",
+                dims.x(),
+                dims.y(),
+                dims.z(),
+                bytes >> 20
+            );
+        }
+    }
+    let grad = cli.color_gradient.gradient();
+    let mut auto_system = AutoSystem3d::new_from_dims(&dims);
+    auto_system.set_stepping(cli.stepping);
+    auto_system.set_boundaries(cli.boundary);
+    auto_system.set_scheme(cli.update);
+    let min = cube_density(&dims, cli.minimum);
+    let max = cube_density(&dims, cli.maximum);
+    // every competing species gets its own colors
+    let species = rules.competition().map_or(0, |c| c.species().len());
     // manage plugins and fullscreen mode
     let plugins = if cli.fullscreen {
         DefaultPlugins.set(WindowPlugin {
@@ -55,54 +78,61 @@ fn main() {
 
     let mut app = App::new();
 
-    match &cli.light_mode {
-        LightMode::Bloom => {
-            app.insert_resource(ClearColor(Color::srgb(0.15, 0.15, 0.15)));
-        }
-        _ => {}
+    // the edge length only defines the initially populated box if the
+    // system is unbounded
+    if cli.unbounded {
+        let mut sparse = SparseSystem3d::new();
+        sparse.set_scheme(cli.update);
+        app.insert_resource(sparse);
+    } else {
+        app.insert_resource(auto_system);
     }
 
+    app.insert_resource(cli.light_mode.clear_color());
+
     app.add_plugins(plugins)
         .add_plugins(PanOrbitCameraPlugin)
-        .add_systems(Startup, setup)
+        .add_systems(Startup, (setup, jump_ahead).chain())
         .add_systems(
             Update,
             (
                 update_system,
                 spawn_new_at_keystroke,
                 adjust_timer,
+                cycle_preset,
+                reverse_time,
                 show_helptext,
                 manage_panorbit,
+                focus_camera,
                 quit,
             ),
         )
-        .insert_resource(auto_system)
+        .insert_resource(CubeEntities::default())
         .insert_resource(SystemTimer::millis(125))
         .insert_resource(GlobalData::new(cli.seed))
-        .insert_resource(GlobalStatic::new(grad, dims, min, max))
+        .insert_resource(GlobalStatic::new(grad, dims, min, max).with_species(species))
         .insert_resource(rules)
         .insert_resource(cli);
 
     app.run();
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup(
     mut commands: Commands,
-    para_commands: ParallelCommands,
+    cubes: ResMut<CubeEntities>,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
     glstat: Res<GlobalStatic>,
-    auto_system: ResMut<AutoSystem3d>,
+    auto_system: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
     global_data: ResMut<GlobalData>,
     mut ambient_light: ResMut<AmbientLight>,
     rules: Res<Rules>,
     cli: Res<Cli>,
 ) {
     // light, dependent on settings
-    let (illuminance, ambi) = match cli.light_mode {
-        LightMode::Bloom => (1000.0, 250.0),
-        LightMode::Normal => (2000.0, 500.0),
-    };
+    let (illuminance, ambi) = cli.light_mode.intensity();
     commands.spawn((
         DirectionalLight {
             illuminance,
@@ -112,11 +142,13 @@ fn setup(
     ));
     ambient_light.brightness = ambi;
 
-    // (panorbit)camera
+    // (panorbit)camera, keep large systems in view
+    let dims = glstat.dims();
+    let zoom = (dims.x().max(dims.y()).max(dims.z()) as f32 / 64.0).max(1.0);
     match cli.light_mode {
         LightMode::Bloom => {
             commands.spawn((
-                Transform::from_translation(Vec3::new(0.0, 1.5, 15.0)),
+                Transform::from_translation(Vec3::new(0.0, 1.5, 15.0) * zoom),
                 PanOrbitCamera::default(),
                 Camera {
                     hdr: true,
@@ -128,7 +160,7 @@ fn setup(
         }
         LightMode::Normal => {
             commands.spawn((
-                Transform::from_translation(Vec3::new(10.0, 2.5, 10.0)),
+                Transform::from_translation(Vec3::new(10.0, 2.5, 10.0) * zoom),
                 PanOrbitCamera::default(),
             ));
         }
@@ -136,10 +168,12 @@ fn setup(
 
     // initial fill
     spawn_pseudorandom_full(
-        para_commands,
+        commands,
+        cubes,
         meshes,
         materials,
         auto_system,
+        sparse,
         global_data,
         rules,
         glstat,
@@ -0,0 +1,394 @@
+use crate::system::MAX_LIFE;
+
+// partitioning rules: instead of counting neighbours, the system is split
+// into blocks of 2x2x2 cells, and every block as a whole is replaced by a
+// new configuration
+// the blocks are shifted by one cell along every axis in every other
+// generation, so cubes can travel from block to block
+// in the text format, every line starting with 'map' gives the new
+// configuration of a block, i.e. the occupied cells before and after,
+// at positions 0 or 1 along every axis, e.g.
+//   states 2
+//   symmetry y
+//   map 0,1,0 -> 0,0,0
+//   map 0,1,0 0,1,1 -> 0,0,0 0,0,1
+// where a cube falls down if the cell below it is empty
+// configurations that are not mapped stay as they are
+// 'symmetry' maps the rotations and reflections of every configuration
+// as well: 'none' (default), 'y' for those that keep the y axis pointing
+// up, 'all' for all 48 of them
+// empty lines and anything after '#' are ignored
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BlockRule {
+    // where the rule came from, e.g. the path of the file
+    source: String,
+    states: isize,
+    // new configuration of every configuration, bit 4x + 2y + z of a
+    // configuration is set if the cell at (x, y, z) of the block is occupied
+    table: [u8; 256],
+    // number of listed mappings
+    mappings: usize,
+}
+
+impl BlockRule {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::parse(path, &text).map_err(|e| format!("{path}: {e}"))
+    }
+    pub fn parse(source: &str, text: &str) -> Result<Self, String> {
+        let mut states = None;
+        let mut symmetry = "none";
+        // line number and configurations of every listed mapping
+        let mut mappings = Vec::new();
+        for (l, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "" => {}
+                "states" => {
+                    let s = value
+                        .parse::<isize>()
+                        .ok()
+                        .filter(|s| (2..=MAX_LIFE + 2).contains(s))
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: expected between 2 and {} states",
+                                l + 1,
+                                MAX_LIFE + 2
+                            )
+                        })?;
+                    states = Some(s);
+                }
+                "symmetry" => {
+                    symmetry = match value {
+                        "none" | "y" | "all" => value,
+                        _ => return Err(format!("line {}: expected none, y or all", l + 1)),
+                    };
+                }
+                "map" => {
+                    let (before, after) = value
+                        .split_once("->")
+                        .ok_or_else(|| format!("line {}: expected '<cells> -> <cells>'", l + 1))?;
+                    mappings.push((l, parse_block(l, before)?, parse_block(l, after)?));
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: expected 'states', 'symmetry' or 'map'",
+                        l + 1
+                    ))
+                }
+            }
+        }
+        let states = states.ok_or("the number of states is missing")?;
+        let symmetries: Vec<[usize; 4]> = symmetries()
+            .into_iter()
+            .filter(|s| match symmetry {
+                "y" => s[1] == 1 && s[3] & 2 == 0,
+                "all" => true,
+                _ => *s == [0, 1, 2, 0],
+            })
+            .collect();
+        // every configuration is mapped at most once, unless both lines
+        // agree on the outcome
+        let mut table = [0u8; 256];
+        let mut mapped_by: [Option<usize>; 256] = [None; 256];
+        for &(l, before, after) in &mappings {
+            for symmetry in &symmetries {
+                let (before, after) = (permute(before, symmetry), permute(after, symmetry));
+                match mapped_by[before as usize] {
+                    Some(m) if m == l && table[before as usize] != after => {
+                        return Err(format!(
+                            "line {}: the rotations and reflections of this mapping contradict each other",
+                            l + 1
+                        ))
+                    }
+                    Some(m) if table[before as usize] != after => {
+                        return Err(format!(
+                            "line {}: maps a configuration differently than line {}",
+                            l + 1,
+                            m + 1
+                        ))
+                    }
+                    Some(_) => {}
+                    None => {
+                        table[before as usize] = after;
+                        mapped_by[before as usize] = Some(l);
+                    }
+                }
+            }
+        }
+        for (c, mapped_by) in mapped_by.iter().enumerate() {
+            if mapped_by.is_none() {
+                table[c] = c as u8;
+            }
+        }
+        Ok(Self {
+            source: source.to_string(),
+            states,
+            table,
+            mappings: mappings.len(),
+        })
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn states(&self) -> isize {
+        self.states
+    }
+    pub fn mappings(&self) -> usize {
+        self.mappings
+    }
+    // new configuration of a block, see 'table'
+    pub fn apply(&self, configuration: u8) -> u8 {
+        self.table[configuration as usize]
+    }
+    // every configuration results from exactly one configuration, so the
+    // system can be run backwards
+    pub fn is_reversible(&self) -> bool {
+        let mut seen = [false; 256];
+        self.table
+            .iter()
+            .all(|&c| !std::mem::replace(&mut seen[c as usize], true))
+    }
+    // every block keeps its number of cubes
+    pub fn conserves_cubes(&self) -> bool {
+        self.table
+            .iter()
+            .enumerate()
+            .all(|(c, &n)| (c as u8).count_ones() == n.count_ones())
+    }
+}
+
+// bit of the cell at (x, y, z) within a block
+pub fn block_bit(x: usize, y: usize, z: usize) -> usize {
+    4 * x + 2 * y + z
+}
+
+fn parse_block(l: usize, cells: &str) -> Result<u8, String> {
+    let mut configuration = 0u8;
+    for token in cells.split_whitespace() {
+        let c: Vec<usize> = token
+            .split(',')
+            .map(|c| c.parse().ok().filter(|&c| c < 2))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("line {}: '{token}' is not a cell of a block", l + 1))?;
+        match c[..] {
+            [x, y, z] => configuration |= 1 << block_bit(x, y, z),
+            _ => {
+                return Err(format!(
+                    "line {}: '{token}' is not a cell of a block",
+                    l + 1
+                ))
+            }
+        }
+    }
+    Ok(configuration)
+}
+
+// the 48 symmetries of a block, i.e. the axis every axis is taken from,
+// and a bit for every axis that is flipped
+fn symmetries() -> Vec<[usize; 4]> {
+    let axes = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+    axes.iter()
+        .flat_map(|a| (0..8).map(move |flips| [a[0], a[1], a[2], flips]))
+        .collect()
+}
+
+fn permute(configuration: u8, symmetry: &[usize; 4]) -> u8 {
+    let mut image = 0;
+    for bit in (0..8).filter(|b| configuration >> b & 1 == 1) {
+        let c = [bit >> 2 & 1, bit >> 1 & 1, bit & 1];
+        let flip = |a: usize| symmetry[3] >> a & 1;
+        let (x, y, z) = (
+            c[symmetry[0]] ^ flip(0),
+            c[symmetry[1]] ^ flip(1),
+            c[symmetry[2]] ^ flip(2),
+        );
+        image |= 1 << block_bit(x, y, z);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::{Boundaries, Boundary};
+    use crate::rules::Rules;
+    use crate::sparse::SparseSystem3d;
+    use crate::system::{AutoSystem3d, Automaton, Noise, SystemDims};
+
+    fn parse(text: &str) -> Result<BlockRule, String> {
+        BlockRule::parse("test", text)
+    }
+
+    // a single cube at (x, y, z)
+    fn cube(x: usize, y: usize, z: usize) -> u8 {
+        1 << block_bit(x, y, z)
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+        assert!(error("map 0,0,0 -> 1,0,0").contains("states"));
+        assert!(error("states 1").contains("states"));
+        assert!(error("states 2\nsymmetry z").contains("line 2"));
+        assert!(error("states 2\nmap 2,0,0 -> 1,0,0").contains("'2,0,0'"));
+        assert!(error("states 2\nmap 0,0 -> 1,0,0").contains("'0,0'"));
+        assert!(error("states 2\nmap 0,0,0 1,0,0").contains("'<cells> -> <cells>'"));
+        assert!(error("states 2\nrule 0,0,0 -> 1,0,0").contains("line 2"));
+        // a single cube cannot move along x and y at once
+        assert_eq!(
+            error("states 2\nsymmetry all\nmap 0,0,0 -> 1,0,0"),
+            "line 3: the rotations and reflections of this mapping contradict each other"
+        );
+        assert_eq!(
+            error("states 2\nmap 0,0,0 -> 1,0,0\n\nmap 0,0,0 -> 0,1,0"),
+            "line 4: maps a configuration differently than line 2"
+        );
+        // lines that agree are fine
+        let rule = parse("states 2\nmap 0,0,0 -> 1,0,0\nmap 0,0,0 -> 1,0,0 # again").unwrap();
+        assert_eq!(rule.mappings(), 2);
+        assert_eq!(rule.apply(cube(0, 0, 0)), cube(1, 0, 0));
+    }
+
+    #[test]
+    fn parse_symmetry() {
+        // a cube falls down, the y axis keeps pointing up
+        let rule = parse("states 2\nsymmetry y\nmap 0,1,0 -> 0,0,0").unwrap();
+        for (x, z) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert_eq!(rule.apply(cube(x, 1, z)), cube(x, 0, z));
+            assert_eq!(rule.apply(cube(x, 0, z)), cube(x, 0, z));
+        }
+        assert_eq!(rule.apply(0b11), 0b11);
+        // a cube jumps to the opposite corner, whichever corner it is in
+        let rule = parse("states 3\nsymmetry all\nmap 0,0,0 -> 1,1,1").unwrap();
+        assert_eq!(rule.states(), 3);
+        assert_eq!(rule.mappings(), 1);
+        for b in 0..8 {
+            assert_eq!(rule.apply(1 << b), 1 << (7 - b));
+        }
+        assert_eq!(rule.apply(0b11), 0b11);
+        // without any symmetry only the listed configuration is mapped
+        let rule = parse("states 2\nmap 0,1,0 -> 0,0,0").unwrap();
+        assert_eq!(rule.apply(cube(0, 1, 0)), cube(0, 0, 0));
+        assert_eq!(rule.apply(cube(1, 1, 0)), cube(1, 1, 0));
+    }
+
+    #[test]
+    fn permutations() {
+        let identity = [0, 1, 2, 0];
+        let images = |s: &[usize; 4]| (0..8).map(|b| permute(1 << b, s)).collect::<Vec<_>>();
+        assert_eq!(
+            images(&identity),
+            (0..8).map(|b| 1 << b).collect::<Vec<u8>>()
+        );
+        assert_eq!(permute(cube(0, 0, 0), &[0, 1, 2, 1]), cube(1, 0, 0));
+        assert_eq!(permute(cube(0, 0, 0), &[0, 1, 2, 4]), cube(0, 0, 1));
+        assert_eq!(permute(cube(1, 0, 0), &[2, 1, 0, 0]), cube(0, 0, 1));
+        assert_eq!(permute(cube(1, 1, 0), &[1, 2, 0, 0]), cube(1, 0, 1));
+        // 48 distinct permutations of the cells, which keep the number
+        // of cubes
+        let symmetries = symmetries();
+        let mut all: Vec<Vec<u8>> = symmetries.iter().map(images).collect();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 48);
+        for s in &symmetries {
+            assert!((0..=255u8).all(|c| permute(c, s).count_ones() == c.count_ones()));
+        }
+        // a single cube may end up in any corner, three cubes of one face
+        // in 24 ways
+        let orbit = |c: u8| {
+            let mut images: Vec<u8> = symmetries.iter().map(|s| permute(c, s)).collect();
+            images.sort();
+            images.dedup();
+            images.len()
+        };
+        assert_eq!(orbit(cube(0, 0, 0)), 8);
+        assert_eq!(orbit(cube(0, 0, 0) | cube(0, 0, 1) | cube(0, 1, 0)), 24);
+        assert_eq!(orbit(0xff), 1);
+    }
+
+    #[test]
+    fn reversible_and_conserving() {
+        let falling = parse("states 2\nsymmetry y\nmap 0,1,0 -> 0,0,0").unwrap();
+        assert!(!falling.is_reversible());
+        assert!(falling.conserves_cubes());
+        let swap = parse("states 2\nmap 0,1,0 -> 0,0,0\nmap 0,0,0 -> 0,1,0").unwrap();
+        assert!(swap.is_reversible());
+        assert!(swap.conserves_cubes());
+        let growing = parse("states 2\nmap 0,0,0 -> 0,0,0 1,1,1").unwrap();
+        assert!(!growing.is_reversible());
+        assert!(!growing.conserves_cubes());
+        // swapping an empty block with a full one
+        let flash = parse("states 2\nmap -> 0,0,0 0,0,1 0,1,0 0,1,1 1,0,0 1,0,1 1,1,0 1,1,1\nmap 0,0,0 0,0,1 0,1,0 0,1,1 1,0,0 1,0,1 1,1,0 1,1,1 ->").unwrap();
+        assert!(flash.is_reversible());
+        assert!(!flash.conserves_cubes());
+    }
+
+    // blocks start at even coordinates in even generations and at odd
+    // ones in odd generations, so a cube that jumps to the opposite
+    // corner of its block keeps moving
+    #[test]
+    fn alternating_blocks() {
+        let rules =
+            Rules::partitioning(parse("states 2\nsymmetry all\nmap 0,0,0 -> 1,1,1").unwrap());
+        let dims = SystemDims::new(8, 8, 8);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.set_boundaries(Boundaries::all(Boundary::Dead));
+        system.set_xyz((2, 2, 2), Automaton::new(0));
+        let mut sparse = SparseSystem3d::from_dense(&system);
+        for g in 0..4 {
+            system.step(&rules, &dims, true, Noise::new(0, g));
+            sparse.step(&rules, true, Noise::new(0, g));
+            let c = 3 + g as usize;
+            assert_eq!(system.get_at_xyz((c, c, c)), Some(Automaton::new(0)));
+            assert_eq!(system.population(), 1);
+            let c = c as isize;
+            assert_eq!(sparse.cells().map(|c| c.0).collect::<Vec<_>>(), [(c, c, c)]);
+        }
+    }
+
+    // the inverse rule, applied to the partitions in reverse order, runs
+    // the system back to its initial state
+    #[test]
+    fn runs_back() {
+        let forward = "states 2\nmap 0,0,0 -> 1,0,0\nmap 1,0,0 -> 1,1,0\nmap 1,1,0 -> 0,0,0";
+        let backward = "states 2\nmap 1,0,0 -> 0,0,0\nmap 1,1,0 -> 1,0,0\nmap 0,0,0 -> 1,1,0";
+        let (forward, backward) = (parse(forward).unwrap(), parse(backward).unwrap());
+        assert!(forward.is_reversible() && backward.is_reversible());
+        let (forward, backward) = (Rules::partitioning(forward), Rules::partitioning(backward));
+        let dims = SystemDims::new(8, 6, 10);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.fill_pseudorandom(&dims, 0.2, forward.life(), 3);
+        let cells = |system: &AutoSystem3d| {
+            let mut cells = Vec::new();
+            for i in dims.range_x() {
+                for j in dims.range_y() {
+                    for k in dims.range_z() {
+                        cells.push(system.get_at_xyz((i, j, k)));
+                    }
+                }
+            }
+            cells
+        };
+        let initial = cells(&system);
+        let generations = 9;
+        for g in 0..generations {
+            system.step(&forward, &dims, true, Noise::new(0, g));
+        }
+        assert_ne!(cells(&system), initial);
+        for g in (0..generations).rev() {
+            system.step(&backward, &dims, true, Noise::new(0, g));
+        }
+        assert_eq!(cells(&system), initial);
+    }
+}
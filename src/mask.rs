@@ -0,0 +1,140 @@
+use crate::rules::{MAX_COUNT, MAX_REACH};
+use std::collections::HashSet;
+
+// a neighbourhood defined by the user
+// every line of the text format holds the offset of a neighbour and
+// optionally its weight, e.g. "1 0 -1 2", the weight defaults to 1
+// empty lines and anything after '#' are ignored
+// the neighbour count of a cell is the sum of the weights of all
+// occupied neighbours
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mask {
+    // where the mask came from, e.g. the path of the file
+    source: String,
+    offsets: Vec<(isize, isize, isize)>,
+    weights: Vec<usize>,
+}
+
+impl Mask {
+    // a mask that is known to be valid
+    pub fn new(source: &str, offsets: Vec<(isize, isize, isize)>, weights: Vec<usize>) -> Self {
+        Self {
+            source: source.to_string(),
+            offsets,
+            weights,
+        }
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::parse(path, &text).map_err(|e| format!("{path}: {e}"))
+    }
+    pub fn parse(source: &str, text: &str) -> Result<Self, String> {
+        let mut offsets = Vec::new();
+        let mut weights = Vec::new();
+        let mut seen = HashSet::new();
+        for (l, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            let invalid = || format!("line {}: expected 'dx dy dz [weight]'", l + 1);
+            if fields.len() > 4 || fields.len() < 3 {
+                return Err(invalid());
+            }
+            let mut c = [0isize; 3];
+            for (c, field) in c.iter_mut().zip(&fields) {
+                *c = field.parse().map_err(|_| invalid())?;
+            }
+            if c.iter().any(|c| c.unsigned_abs() > MAX_REACH) {
+                return Err(format!(
+                    "line {}: offsets may reach at most {MAX_REACH} cells",
+                    l + 1
+                ));
+            }
+            let weight = match fields.get(3) {
+                Some(w) => w.parse::<usize>().map_err(|_| invalid())?,
+                None => 1,
+            };
+            if weight == 0 {
+                return Err(format!("line {}: weights must be positive", l + 1));
+            }
+            if !seen.insert(c) {
+                return Err(format!("line {}: duplicate offset", l + 1));
+            }
+            offsets.push((c[0], c[1], c[2]));
+            weights.push(weight);
+        }
+        if offsets.is_empty() {
+            return Err("no offsets".to_string());
+        }
+        // the weights are unbounded on their own, so their sum may overflow
+        let total = weights
+            .iter()
+            .try_fold(0usize, |sum, &w| sum.checked_add(w));
+        if total.is_none_or(|total| total > MAX_COUNT) {
+            return Err(format!("the weights may add up to at most {MAX_COUNT}"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            offsets,
+            weights,
+        })
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn offsets(&self) -> &[(isize, isize, isize)] {
+        &self.offsets
+    }
+    pub fn weights(&self) -> &[usize] {
+        &self.weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Mask, String> {
+        Mask::parse("test", text)
+    }
+
+    #[test]
+    fn weights() {
+        let mask = parse("# a comment\n1 0 -1 2\n\n-8 8 0 # far away\n").unwrap();
+        assert_eq!(mask.source(), "test");
+        assert_eq!(mask.offsets(), [(1, 0, -1), (-8, 8, 0)]);
+        assert_eq!(mask.weights(), [2, 1]);
+        let most = format!("1 0 0 {}\n-1 0 0 1", MAX_COUNT - 1);
+        assert_eq!(parse(&most).unwrap().weights(), [MAX_COUNT - 1, 1]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+        assert_eq!(error("# nothing\n"), "no offsets");
+        assert_eq!(error("1 0"), "line 1: expected 'dx dy dz [weight]'");
+        assert_eq!(error("1 0 0 1 1"), "line 1: expected 'dx dy dz [weight]'");
+        assert_eq!(error("1 0 x"), "line 1: expected 'dx dy dz [weight]'");
+        assert_eq!(error("1 0 0 -1"), "line 1: expected 'dx dy dz [weight]'");
+        assert_eq!(
+            error("1 0 0\n0 1 0 3\n\n1 0 0 2"),
+            "line 4: duplicate offset"
+        );
+        assert_eq!(
+            error("0 0 9"),
+            format!("line 1: offsets may reach at most {MAX_REACH} cells")
+        );
+        assert_eq!(
+            error("1 1 1\n-9 0 0"),
+            format!("line 2: offsets may reach at most {MAX_REACH} cells")
+        );
+        assert_eq!(error("1 0 0\n0 1 0 0"), "line 2: weights must be positive");
+        // sums beyond the largest count, and beyond the largest number
+        let too_many = format!("the weights may add up to at most {MAX_COUNT}");
+        assert_eq!(error(&format!("1 0 0 {MAX_COUNT}\n-1 0 0 1")), too_many);
+        let overflow = format!("1 0 0 {}\n-1 0 0 {}", usize::MAX, usize::MAX);
+        assert_eq!(error(&overflow), too_many);
+    }
+}
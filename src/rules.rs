@@ -1,17 +1,107 @@
+use crate::margolus::BlockRule;
+use crate::mask::Mask;
+use crate::species::{self, Competition};
+use crate::system::MAX_LIFE;
+use crate::table::Table;
+#[cfg(feature = "render")]
 use bevy::prelude::Resource;
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, Resource)]
+// largest distance along an axis between a cell and its neighbours
+pub const MAX_REACH: usize = 8;
+// largest possible neighbour count
+pub const MAX_COUNT: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub enum Neighbourhood {
-    Moore,
-    VonNeumann,
+    // every cell within a cube of edge length 2r + 1 around the cell
+    Moore(usize),
+    // every cell within a Manhattan distance of r
+    VonNeumann(usize),
+    // the 18 cells that share a face or an edge with the cell
+    FaceEdge,
+    // offsets and weights defined by the user
+    Custom(Arc<Mask>),
 }
 
 impl Neighbourhood {
-    pub fn parse_from_str(input: &str) -> Self {
-        match input {
-            "VN" | "vn" => Self::VonNeumann,
-            _ => Self::Moore,
+    // besides 'M' and 'VN', the letters used by Golly and LifeViewer
+    // are accepted as well, e.g. 'NM', 'NN' or 'V'
+    // a range may be appended, e.g. 'M2' or 'VN3', 'N18' stands for the
+    // face and edge neighbourhood
+    // custom neighbourhoods need to be loaded, see 'Mask'
+    pub fn parse_from_str(input: &str) -> Option<Self> {
+        if matches!(input, "N18" | "n18") {
+            return Some(Self::FaceEdge);
+        }
+        let name = input.trim_end_matches(|c: char| c.is_ascii_digit());
+        let range = match &input[name.len()..] {
+            "" => 1,
+            digits => digits.parse().ok().filter(|&r| r > 0)?,
+        };
+        match name {
+            "M" | "m" | "NM" | "nm" | "Moore" | "moore" => Some(Self::Moore(range)),
+            "VN" | "vn" | "NN" | "nn" | "V" | "v" | "NV" | "nv" => Some(Self::VonNeumann(range)),
+            _ => None,
+        }
+    }
+    // relative coordinates of all neighbours
+    pub fn offsets(&self) -> Vec<(isize, isize, isize)> {
+        let cube = |r: isize| {
+            (-r..=r).flat_map(move |dx| {
+                (-r..=r).flat_map(move |dy| (-r..=r).map(move |dz| (dx, dy, dz)))
+            })
+        };
+        let distance = |o: &(isize, isize, isize)| o.0.abs() + o.1.abs() + o.2.abs();
+        match self {
+            Neighbourhood::Moore(r) => cube(*r as isize).filter(|o| distance(o) > 0).collect(),
+            Neighbourhood::VonNeumann(r) => cube(*r as isize)
+                .filter(|o| (1..=*r as isize).contains(&distance(o)))
+                .collect(),
+            Neighbourhood::FaceEdge => cube(1).filter(|o| (1..=2).contains(&distance(o))).collect(),
+            Neighbourhood::Custom(mask) => mask.offsets().to_vec(),
+        }
+    }
+    // how much every neighbour adds to the neighbour count, in the order
+    // of 'offsets'
+    pub fn weights(&self) -> Vec<usize> {
+        match self {
+            Neighbourhood::Custom(mask) => mask.weights().to_vec(),
+            _ => vec![1; self.size()],
+        }
+    }
+    // number of cells within the neighbourhood
+    pub fn size(&self) -> usize {
+        match self {
+            Neighbourhood::Moore(r) => (2 * r + 1).pow(3) - 1,
+            Neighbourhood::FaceEdge => 18,
+            _ => self.offsets().len(),
+        }
+    }
+    // the largest possible neighbour count
+    pub fn max_count(&self) -> usize {
+        match self {
+            Neighbourhood::Custom(mask) => mask.weights().iter().sum(),
+            _ => self.size(),
+        }
+    }
+    // largest distance along an axis between a cell and its neighbours
+    pub fn reach(&self) -> usize {
+        match self {
+            Neighbourhood::Moore(r) | Neighbourhood::VonNeumann(r) => *r,
+            Neighbourhood::FaceEdge => 1,
+            Neighbourhood::Custom(mask) => mask
+                .offsets()
+                .iter()
+                .map(|o| {
+                    o.0.unsigned_abs()
+                        .max(o.1.unsigned_abs())
+                        .max(o.2.unsigned_abs())
+                })
+                .max()
+                .unwrap_or(0),
         }
     }
 }
@@ -19,75 +109,806 @@ impl Neighbourhood {
 impl fmt::Display for Neighbourhood {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Neighbourhood::Moore => write!(f, "Moore"),
-            Neighbourhood::VonNeumann => write!(f, "Von Neumann"),
+            Neighbourhood::Moore(1) => write!(f, "Moore"),
+            Neighbourhood::Moore(r) => write!(f, "Moore (range {r})"),
+            Neighbourhood::VonNeumann(1) => write!(f, "Von Neumann"),
+            Neighbourhood::VonNeumann(r) => write!(f, "Von Neumann (range {r})"),
+            Neighbourhood::FaceEdge => write!(f, "face and edge"),
+            Neighbourhood::Custom(mask) => write!(f, "custom ({})", mask.source()),
         }
     }
 }
 
-#[derive(Debug, Clone, Resource)]
+// how the rules are interpreted
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(clap::ValueEnum))]
+pub enum Semantics {
+    /// every cube counts as a neighbour, a decaying cube that meets the
+    /// survival condition again stops decaying, new cubes need a
+    /// neighbour count within both the survival and the spawn range
+    #[default]
+    Legacy,
+    /// 'Generations' as found in the literature: only cubes with full life
+    /// count as neighbours, once a cube loses life it decays until it
+    /// is gone, new cubes need a neighbour count within the spawn range
+    Standard,
+}
+
+impl fmt::Display for Semantics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Semantics::Legacy => write!(f, "legacy"),
+            Semantics::Standard => write!(f, "standard"),
+        }
+    }
+}
+
+// counts a condition may refer to: the number of all neighbours, or only
+// of those above, below or lateral to the cell (see 'Direction'), or of
+// those that share a face, an edge or a corner with it (see 'Split')
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Count {
+    Total,
+    Above,
+    Below,
+    Lateral,
+    Face,
+    Edge,
+    Corner,
+}
+
+impl Count {
+    const ALL: [Count; 7] = [
+        Count::Total,
+        Count::Above,
+        Count::Below,
+        Count::Lateral,
+        Count::Face,
+        Count::Edge,
+        Count::Corner,
+    ];
+
+    // letter in front of the ranges of a count within a condition
+    fn prefix(&self) -> &'static str {
+        match self {
+            Count::Total => "",
+            Count::Above => "u",
+            Count::Below => "d",
+            Count::Lateral => "l",
+            Count::Face => "f",
+            Count::Edge => "e",
+            Count::Corner => "c",
+        }
+    }
+    // the part of the neighbourhood that is counted, see 'Split::part'
+    fn part(&self) -> Option<usize> {
+        match self {
+            Count::Total => None,
+            Count::Above | Count::Face => Some(0),
+            Count::Below | Count::Edge => Some(1),
+            Count::Lateral | Count::Corner => Some(2),
+        }
+    }
+    fn is_directional(&self) -> bool {
+        matches!(self, Count::Above | Count::Below | Count::Lateral)
+    }
+    // the neighbours that are counted, e.g. "the largest count {}"
+    fn describe(&self) -> &'static str {
+        match self {
+            Count::Total => "of all neighbours",
+            Count::Above => "above a cell",
+            Count::Below => "below a cell",
+            Count::Lateral => "lateral to a cell",
+            Count::Face => "of face neighbours",
+            Count::Edge => "of edge neighbours",
+            Count::Corner => "of corner neighbours",
+        }
+    }
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Count::Total => write!(f, "total"),
+            Count::Above => write!(f, "above"),
+            Count::Below => write!(f, "below"),
+            Count::Lateral => write!(f, "lateral"),
+            Count::Face => write!(f, "face"),
+            Count::Edge => write!(f, "edge"),
+            Count::Corner => write!(f, "corner"),
+        }
+    }
+}
+
+// the probability of an event in millionths, e.g. of a cube surviving
+// or spawning with a neighbour count within a range like "5@0.7"
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Chance(u32);
+
+impl Chance {
+    const SCALE: u32 = 1_000_000;
+    pub const NEVER: Self = Self(0);
+    pub const HALF: Self = Self(Self::SCALE / 2);
+    pub const ALWAYS: Self = Self(Self::SCALE);
+
+    // a decimal between 0 and 1 with at most 6 decimal places, e.g. "0.7"
+    pub fn parse_from_str(input: &str) -> Option<Self> {
+        let (whole, fraction) = input.trim().split_once('.').unwrap_or((input.trim(), ""));
+        if fraction.len() > 6 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let whole = match whole {
+            "" if !fraction.is_empty() => 0,
+            whole => whole.parse::<u32>().ok()?,
+        };
+        let millionths = format!("{fraction:0<6}").parse::<u32>().ok()?;
+        let chance = whole.checked_mul(Self::SCALE)?.checked_add(millionths)?;
+        (chance <= Self::SCALE).then_some(Self(chance))
+    }
+    // whether the event occurs, given a uniformly distributed random number
+    pub fn occurs(&self, random: u64) -> bool {
+        random % u64::from(Self::SCALE) < u64::from(self.0)
+    }
+    // the event either always or never occurs
+    pub fn is_certain(&self) -> bool {
+        *self == Self::NEVER || *self == Self::ALWAYS
+    }
+    // the chance of both independent events occurring
+    fn and(self, other: Self) -> Self {
+        Self((self.0 as u64 * other.0 as u64 / Self::SCALE as u64) as u32)
+    }
+}
+
+impl fmt::Display for Chance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (whole, fraction) = (self.0 / Self::SCALE, self.0 % Self::SCALE);
+        match fraction {
+            0 => write!(f, "{whole}"),
+            _ => write!(
+                f,
+                "{whole}.{}",
+                format!("{fraction:06}").trim_end_matches('0')
+            ),
+        }
+    }
+}
+
+// a range of neighbour counts and the chance of the outcome within it
+pub type Range = (usize, usize, Chance);
+
+// the neighbour counts that meet a condition, e.g. "4-6,d1-9": a total
+// count of 4 to 6, of which 1 to 9 neighbours are below the cell
+// there are ranges for every count the condition refers to, indexed by
+// 'Count', counts without any ranges are not restricted
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Condition {
+    ranges: [Option<Vec<Range>>; 7],
+}
+
+impl Condition {
+    fn total(ranges: Vec<(usize, usize)>) -> Self {
+        let mut condition = Self::default();
+        condition.ranges[Count::Total as usize] = Some(
+            ranges
+                .into_iter()
+                .map(|(a, b)| (a, b, Chance::ALWAYS))
+                .collect(),
+        );
+        condition
+    }
+    // 'counts' are indexed by 'Count'
+    pub fn contains(&self, counts: [usize; 7]) -> bool {
+        self.chance(counts) != Chance::NEVER
+    }
+    // the chance of the outcome, i.e. the product of the chances of the
+    // first range containing each count, or never if a count is not
+    // contained in any range
+    pub fn chance(&self, counts: [usize; 7]) -> Chance {
+        self.ranges
+            .iter()
+            .zip(counts)
+            .fold(Chance::ALWAYS, |chance, (ranges, n)| match ranges {
+                None => chance,
+                Some(ranges) => chance.and(
+                    ranges
+                        .iter()
+                        .find(|r| (r.0..=r.1).contains(&n))
+                        .map_or(Chance::NEVER, |r| r.2),
+                ),
+            })
+    }
+    // the outcome depends on nothing but the neighbour counts
+    pub fn is_certain(&self) -> bool {
+        self.ranges
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|r| r.2.is_certain())
+    }
+    // all counts the condition refers to
+    pub fn counts(&self) -> impl Iterator<Item = Count> + '_ {
+        Count::ALL
+            .into_iter()
+            .filter(|&c| self.ranges[c as usize].is_some())
+    }
+    pub fn ranges(&self, count: Count) -> Option<&[Range]> {
+        self.ranges[count as usize].as_deref()
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = Count::ALL
+            .iter()
+            .filter_map(|&c| {
+                let ranges = self.ranges(c)?;
+                Some(format_condis(c, ranges))
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+// a direction like gravity or wind, which tells apart the neighbours
+// above, below and lateral to a cell
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Direction(isize, isize, isize);
+
+impl Direction {
+    // the y axis points upwards on screen
+    pub const UP: Self = Self(0, 1, 0);
+
+    // an axis like "y" or "-x", or a vector like "1,0,-1"
+    pub fn parse_from_str(input: &str) -> Option<Self> {
+        let (sign, axis) = match input.trim() {
+            s if s.starts_with('-') => (-1, &s[1..]),
+            s => (1, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let direction = match axis {
+            "x" | "X" => Self(sign, 0, 0),
+            "y" | "Y" => Self(0, sign, 0),
+            "z" | "Z" => Self(0, 0, sign),
+            _ => {
+                let c: Vec<isize> = input
+                    .split(',')
+                    .map(|c| c.trim().parse().ok())
+                    .collect::<Option<_>>()?;
+                let [x, y, z] = c[..] else {
+                    return None;
+                };
+                Self(x, y, z)
+            }
+        };
+        (direction != Self(0, 0, 0)).then_some(direction)
+    }
+    // which side of a cell a neighbour at 'offset' lies on
+    pub fn side(&self, offset: (isize, isize, isize)) -> Count {
+        match offset.0 * self.0 + offset.1 * self.1 + offset.2 * self.2 {
+            d if d > 0 => Count::Above,
+            d if d < 0 => Count::Below,
+            _ => Count::Lateral,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self(x, 0, 0) if x.abs() == 1 => write!(f, "{}x", if x < 0 { "-" } else { "" }),
+            Self(0, y, 0) if y.abs() == 1 => write!(f, "{}y", if y < 0 { "-" } else { "" }),
+            Self(0, 0, z) if z.abs() == 1 => write!(f, "{}z", if z < 0 { "-" } else { "" }),
+            Self(x, y, z) => write!(f, "{x},{y},{z}"),
+        }
+    }
+}
+
+// how conditions split the neighbourhood of a cell into three parts,
+// whose counts they refer to separately
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Split {
+    // above, below and lateral to the cell
+    Direction(Direction),
+    // neighbours that share a face, an edge or a corner with the cell,
+    // i.e. whose offsets are non-zero along one, two or three axes
+    Shape,
+}
+
+impl Split {
+    // the part a neighbour at 'offset' belongs to
+    pub fn part(&self, offset: (isize, isize, isize)) -> usize {
+        let count = match self {
+            Split::Direction(direction) => direction.side(offset),
+            Split::Shape => match [offset.0, offset.1, offset.2]
+                .iter()
+                .filter(|&&c| c != 0)
+                .count()
+            {
+                1 => Count::Face,
+                2 => Count::Edge,
+                _ => Count::Corner,
+            },
+        };
+        count.part().unwrap_or_default()
+    }
+    // largest weighted counts of the parts
+    fn limits(&self, neighbourhood: &Neighbourhood) -> [usize; 3] {
+        let mut limits = [0; 3];
+        for (o, w) in neighbourhood
+            .offsets()
+            .into_iter()
+            .zip(neighbourhood.weights())
+        {
+            limits[self.part(o)] += w;
+        }
+        limits
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Split::Direction(_) => write!(f, "above, below and lateral to a cell"),
+            Split::Shape => write!(f, "sharing a face, an edge or a corner with a cell"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub struct Rules {
-    survive: Vec<(usize, usize)>,
-    spawn: Vec<(usize, usize)>,
+    survive: Condition,
+    spawn: Condition,
     life: isize,
     neighbourhood: Neighbourhood,
+    // only set if the conditions refer to parts of the neighbourhood
+    split: Option<Split>,
+    // largest counts of these parts
+    parts: [usize; 3],
+    // isotropic non-totalistic rules replace the conditions
+    table: Option<Arc<Table>>,
+    // partitioning rules replace neighbour counting altogether
+    blocks: Option<Arc<BlockRule>>,
+    // competing species replace the rules as well, see 'Competition'
+    competition: Option<Arc<Competition>>,
+    // chance of a cube to lose life regardless of its neighbours
+    decay: Chance,
+    semantics: Semantics,
+    heal: isize,
 }
 
 impl Default for Rules {
     fn default() -> Self {
         Self {
-            survive: vec![(5, 10)],
-            spawn: vec![(8, 8)],
+            survive: Condition::total(vec![(5, 10)]),
+            spawn: Condition::total(vec![(8, 8)]),
             life: 5,
-            neighbourhood: Neighbourhood::Moore,
+            neighbourhood: Neighbourhood::Moore(1),
+            split: None,
+            parts: [0; 3],
+            table: None,
+            blocks: None,
+            competition: None,
+            decay: Chance::NEVER,
+            semantics: Semantics::Legacy,
+            heal: 0,
         }
     }
 }
 
 impl Rules {
-    pub fn parse_from_str(input: &str) -> Option<Self> {
-        let x: Vec<_> = input.split('/').collect();
-        if let (Some(first), Some(second), Some(third), Some(fourth)) =
-            (x.get(0), x.get(1), x.get(2), x.get(3))
-        {
-            if let Some(life) = third.parse::<isize>().ok() {
-                if life > 1 {
-                    Some(Self {
-                        survive: parse_condis(first),
-                        spawn: parse_condis(second),
-                        // subtracting 2 because we already start with two states: Some and None
-                        // the value provided here resembles additional states
-                        life: life - 2,
-                        neighbourhood: Neighbourhood::parse_from_str(fourth),
+    // parse rules in either notation, see README
+    // "5-6/5/3/M" (survival/spawn/states/neighbourhood) or
+    // "B5/S5-6/C3/NM" (birth/survival/states/neighbourhood)
+    // or load a table of isotropic non-totalistic rules, e.g. "table:file"
+    // or the block rule of a partitioning automaton, e.g. "margolus:file"
+    pub fn parse_from_str(input: &str) -> Result<Self, RuleParseError> {
+        if let Some(path) = input.trim().strip_prefix("margolus:") {
+            let blocks =
+                BlockRule::load(path).map_err(|message| RuleParseError::Blocks { message })?;
+            return Ok(Self::partitioning(blocks));
+        }
+        if let Some(path) = input.trim().strip_prefix("table:") {
+            let table = Table::load(path).map_err(|message| RuleParseError::Table { message })?;
+            return Ok(Self {
+                survive: Condition::default(),
+                spawn: Condition::default(),
+                life: table.states() - 2,
+                neighbourhood: table.neighbourhood().clone(),
+                table: Some(Arc::new(table)),
+                ..Self::default()
+            });
+        }
+        // byte offset of every section within the input
+        // the path of a custom neighbourhood may contain slashes, so it
+        // has to be the last section
+        let end = input.find("custom:").unwrap_or(input.len());
+        let mut sections = Vec::new();
+        let mut start = 0;
+        for part in input[..end].split('/') {
+            sections.push((part, start));
+            start += part.len() + 1;
+        }
+        if let Some(last) = sections.last_mut() {
+            last.0 = &input[last.1..];
+        }
+        match sections[0].0.trim_start().chars().next() {
+            Some('B' | 'b' | 'S' | 's') => Self::parse_lettered(&sections),
+            _ => Self::parse_slash(&sections),
+        }
+    }
+
+    // the direction is optional and comes right before the neighbourhood,
+    // e.g. "5-6/d1-9/3/-y/M"
+    fn parse_slash(sections: &[(&str, usize)]) -> Result<Self, RuleParseError> {
+        let (survive, spawn, states, direction, neighbourhood) = match sections[..] {
+            [survive, spawn, states, neighbourhood] => {
+                (survive, spawn, states, None, neighbourhood)
+            }
+            [survive, spawn, states, direction, neighbourhood] => {
+                (survive, spawn, states, Some(direction), neighbourhood)
+            }
+            _ => {
+                return Err(RuleParseError::SectionCount {
+                    found: sections.len(),
+                })
+            }
+        };
+        for (section, (part, position)) in [
+            (Section::States, states),
+            (Section::Direction, direction.unwrap_or(("-", 0))),
+            (Section::Neighbourhood, neighbourhood),
+        ] {
+            if part.trim().is_empty() {
+                return Err(RuleParseError::EmptySection { section, position });
+            }
+        }
+        let direction = direction.map(parse_direction).transpose()?;
+        let neighbourhood = parse_neighbourhood(neighbourhood)?;
+        Self::build(
+            survive,
+            spawn,
+            parse_states(states)?,
+            direction,
+            neighbourhood,
+        )
+    }
+
+    // sections are told apart by their first letter, so their order does
+    // not matter, the states default to 2 and the neighbourhood to Moore
+    // the neighbourhood may also be appended to the conditions, e.g. "S4V"
+    fn parse_lettered(sections: &[(&str, usize)]) -> Result<Self, RuleParseError> {
+        let mut found: [Option<(&str, usize)>; 5] = [None; 5];
+        let mut suffix = None;
+        for &(part, position) in sections {
+            let trimmed = part.trim();
+            let position = position + part.len() - part.trim_start().len();
+            let mut chars = trimmed.chars();
+            let (section, body) = match chars.next().map(|c| c.to_ascii_uppercase()) {
+                _ if trimmed.starts_with("custom:") => {
+                    (Section::Neighbourhood, (trimmed, position))
+                }
+                Some('B') => (Section::Spawn, (chars.as_str(), position + 1)),
+                Some('S') => (Section::Survival, (chars.as_str(), position + 1)),
+                Some('C') => (Section::States, (chars.as_str(), position + 1)),
+                Some('D') => (Section::Direction, (chars.as_str(), position + 1)),
+                Some('N') => (Section::Neighbourhood, (trimmed, position)),
+                Some(c) if c.is_ascii_digit() => (Section::States, (trimmed, position)),
+                _ => {
+                    return Err(RuleParseError::UnknownSection {
+                        position,
+                        token: part.to_string(),
                     })
-                } else {
-                    eprintln!("there must be at least 2 states");
-                    None
                 }
-            } else {
-                eprintln!("failed to parse number of states");
-                None
+            };
+            let body = match section {
+                Section::Spawn | Section::Survival => {
+                    let conditions = body.0.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+                    if conditions.len() < body.0.len() {
+                        suffix = Some((&body.0[conditions.len()..], body.1 + conditions.len()));
+                    }
+                    (conditions, body.1)
+                }
+                _ => body,
+            };
+            let slot = &mut found[section as usize];
+            if slot.is_some() {
+                return Err(RuleParseError::DuplicateSection { section, position });
             }
-        } else {
-            eprintln!("even the basic parsing failed");
-            None
+            *slot = Some(body);
         }
+        let [Some(survive), Some(spawn), states, direction, neighbourhood] = found else {
+            let section = match found[Section::Survival as usize] {
+                None => Section::Survival,
+                Some(_) => Section::Spawn,
+            };
+            return Err(RuleParseError::MissingSection { section });
+        };
+        let states = match states {
+            Some(states) => parse_states(states)?,
+            None => (2, Chance::NEVER),
+        };
+        let direction = direction.map(parse_direction).transpose()?;
+        let neighbourhood = match neighbourhood.or(suffix) {
+            Some(neighbourhood) => parse_neighbourhood(neighbourhood)?,
+            None => Neighbourhood::Moore(1),
+        };
+        Self::build(survive, spawn, states, direction, neighbourhood)
     }
 
+    // the states come with the chance of decay, e.g. "4@0.01"
+    fn build(
+        survive: (&str, usize),
+        spawn: (&str, usize),
+        (states, decay): (isize, Chance),
+        direction: Option<Direction>,
+        neighbourhood: Neighbourhood,
+    ) -> Result<Self, RuleParseError> {
+        // directional conditions refer to 'up' unless stated otherwise
+        let sides = Split::Direction(direction.unwrap_or(Direction::UP)).limits(&neighbourhood);
+        let shapes = Split::Shape.limits(&neighbourhood);
+        let max = neighbourhood.max_count();
+        let limits = [
+            max, sides[0], sides[1], sides[2], shapes[0], shapes[1], shapes[2],
+        ];
+        let survive = parse_condis(survive, Section::Survival, &neighbourhood, limits)?;
+        let spawn = parse_condis(spawn, Section::Spawn, &neighbourhood, limits)?;
+        let counts: Vec<Count> = survive.counts().chain(spawn.counts()).collect();
+        let directional = direction.is_some() || counts.iter().any(Count::is_directional);
+        let shaped = counts
+            .iter()
+            .any(|c| c.part().is_some() && !c.is_directional());
+        let split = match (directional, shaped) {
+            (true, true) => return Err(RuleParseError::MixedSplits),
+            (true, false) => Some(Split::Direction(direction.unwrap_or(Direction::UP))),
+            (false, true) => Some(Split::Shape),
+            (false, false) => None,
+        };
+        let parts = match split {
+            Some(Split::Direction(_)) => sides,
+            Some(Split::Shape) => shapes,
+            None => [0; 3],
+        };
+        // all three counts are packed into a single one, see 'split_count'
+        if let Some(split) = split {
+            if parts.iter().map(|p| p + 1).product::<usize>() > MAX_COUNT + 1 {
+                return Err(RuleParseError::TooLargeToSplit {
+                    neighbourhood,
+                    split,
+                });
+            }
+        }
+        Ok(Self {
+            survive,
+            spawn,
+            // subtracting 2 because we already start with two states: Some and None
+            // the value provided here resembles additional states
+            life: states - 2,
+            neighbourhood,
+            split,
+            parts,
+            table: None,
+            blocks: None,
+            competition: None,
+            decay,
+            semantics: Semantics::default(),
+            heal: 0,
+        })
+    }
+
+    // rules of a partitioning automaton, see 'BlockRule'
+    pub fn partitioning(blocks: BlockRule) -> Self {
+        Self {
+            survive: Condition::default(),
+            spawn: Condition::default(),
+            life: blocks.states() - 2,
+            blocks: Some(Arc::new(blocks)),
+            ..Self::default()
+        }
+    }
+
+    // rules of several competing species, the life is the highest life of
+    // all species
+    pub fn competing(competition: Competition) -> Self {
+        Self {
+            survive: Condition::default(),
+            spawn: Condition::default(),
+            life: competition.life(),
+            semantics: competition.species()[0].semantics(),
+            heal: competition.species()[0].heal(),
+            competition: Some(Arc::new(competition)),
+            ..Self::default()
+        }
+    }
+
+    // the rule string in the given notation
+    pub fn notation(&self, notation: Notation) -> RuleString<'_> {
+        RuleString {
+            rules: self,
+            notation,
+        }
+    }
+
+    // 'n' is a neighbour count as returned by 'pack_count' if the rules
+    // split the neighbourhood, or a configuration if they use a table
+    // for stochastic rules, a cube only despawns if it never survives,
+    // and spawns if it may spawn at all, see 'survive_chance'
     pub fn check_despawn(&self, n: usize) -> bool {
-        check_exclusive(n, &self.survive)
+        match &self.table {
+            Some(table) => !table.survive()[n],
+            None => !self.survive.contains(self.split_count(n)),
+        }
     }
 
     pub fn check_spawn(&self, n: usize) -> bool {
-        check_inclusive(n, &self.spawn)
+        match &self.table {
+            Some(table) => table.spawn()[n],
+            None => self.spawn.contains(self.split_count(n)),
+        }
+    }
+
+    // chance to survive with 'n' neighbours, ignoring decay
+    pub fn survive_chance(&self, n: usize) -> Chance {
+        match &self.table {
+            Some(_) if self.check_despawn(n) => Chance::NEVER,
+            Some(_) => Chance::ALWAYS,
+            None => self.survive.chance(self.split_count(n)),
+        }
+    }
+
+    pub fn spawn_chance(&self, n: usize) -> Chance {
+        match &self.table {
+            Some(_) if self.check_spawn(n) => Chance::ALWAYS,
+            Some(_) => Chance::NEVER,
+            None => self.spawn.chance(self.split_count(n)),
+        }
+    }
+
+    // chance of a cube to lose life during a step regardless of its
+    // neighbours, as if it did not survive
+    pub fn decay(&self) -> Chance {
+        self.decay
+    }
+
+    // the outcome of a step depends on chance, see 'Noise'
+    pub fn is_stochastic(&self) -> bool {
+        if let Some(competition) = &self.competition {
+            return competition.species().iter().any(|r| r.is_stochastic());
+        }
+        self.decay != Chance::NEVER || !self.survive.is_certain() || !self.spawn.is_certain()
+    }
+
+    // the counts of the three parts of a split neighbourhood are packed
+    // into a single neighbour count, so these rules can be stepped just
+    // like any other rules, with the weights of 'counting'
+    pub fn pack_count(&self, parts: [usize; 3]) -> usize {
+        let [a, b, _] = self.parts;
+        parts[0] + (a + 1) * (parts[1] + (b + 1) * parts[2])
+    }
+
+    // inverse of 'pack_count', indexed by 'Count'
+    pub fn split_count(&self, n: usize) -> [usize; 7] {
+        let mut counts = [n, 0, 0, 0, 0, 0, 0];
+        let Some(split) = self.split else {
+            return counts;
+        };
+        let [a, b, _] = self.parts;
+        let parts = [n % (a + 1), n / (a + 1) % (b + 1), n / (a + 1) / (b + 1)];
+        counts[0] = parts.iter().sum();
+        let first = match split {
+            Split::Direction(_) => Count::Above,
+            Split::Shape => Count::Face,
+        };
+        counts[first as usize..first as usize + 3].copy_from_slice(&parts);
+        counts
+    }
+
+    // the neighbourhood neighbours are counted with: the actual one,
+    // unless the rules split it, then the weights are scaled so that
+    // the sum of all weights is the count of 'pack_count', or unless the
+    // rules use a table, then the sum is the configuration
+    pub fn counting(&self) -> Neighbourhood {
+        let (weights, source) = match (&self.table, self.split) {
+            (Some(table), _) => (
+                table.configuration_weights(),
+                "by configuration".to_string(),
+            ),
+            (None, Some(split)) => {
+                let weights = self
+                    .neighbourhood
+                    .offsets()
+                    .into_iter()
+                    .zip(self.neighbourhood.weights())
+                    .map(|(o, w)| {
+                        let mut unit = [0; 3];
+                        unit[split.part(o)] = 1;
+                        w * self.pack_count(unit)
+                    })
+                    .collect();
+                let source = match split {
+                    Split::Direction(direction) => format!("towards {direction}"),
+                    Split::Shape => "by shape".to_string(),
+                };
+                (weights, source)
+            }
+            (None, None) => return self.neighbourhood.clone(),
+        };
+        let offsets = self.neighbourhood.offsets();
+        let source = format!("{} {source}", self.neighbourhood);
+        Neighbourhood::Custom(Arc::new(Mask::new(&source, offsets, weights)))
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        match self.split {
+            Some(Split::Direction(direction)) => Some(direction),
+            _ => None,
+        }
+    }
+
+    pub fn split(&self) -> Option<Split> {
+        self.split
+    }
+
+    pub fn table(&self) -> Option<&Table> {
+        self.table.as_deref()
+    }
+
+    // partitioning rules are stepped block by block instead of cell by
+    // cell, see 'BlockRule'
+    pub fn blocks(&self) -> Option<&BlockRule> {
+        self.blocks.as_deref()
+    }
+
+    // competing species are stepped according to their own rules, see
+    // 'Competition'
+    pub fn competition(&self) -> Option<&Competition> {
+        self.competition.as_deref()
     }
 
     pub fn life(&self) -> isize {
         self.life
     }
 
-    pub fn neighbourhood(&self) -> Neighbourhood {
-        self.neighbourhood
+    pub fn neighbourhood(&self) -> &Neighbourhood {
+        &self.neighbourhood
+    }
+
+    pub fn semantics(&self) -> Semantics {
+        self.semantics
+    }
+
+    pub fn set_semantics(&mut self, semantics: Semantics) {
+        if let Some(competition) = &mut self.competition {
+            Arc::make_mut(competition).set_semantics(semantics);
+        }
+        self.semantics = semantics;
+    }
+
+    // amount of life a weakened cube regains during a step if its
+    // neighbour count is within the survival range, 0 disables healing
+    pub fn heal(&self) -> isize {
+        self.heal
+    }
+
+    pub fn set_heal(&mut self, heal: isize) {
+        if let Some(competition) = &mut self.competition {
+            Arc::make_mut(competition).set_heal(heal);
+        }
+        self.heal = heal.max(0);
+    }
+
+    // new cubes may appear without any neighbours
+    pub fn spawns_on_zero(&self) -> bool {
+        if let Some(blocks) = &self.blocks {
+            return blocks.apply(0) != 0;
+        }
+        // new cubes of competing species need a parent
+        if self.competition.is_some() {
+            return false;
+        }
+        match self.semantics {
+            Semantics::Legacy => !self.check_despawn(0) && self.check_spawn(0),
+            Semantics::Standard => self.check_spawn(0),
+        }
     }
 
     pub fn default_warn() -> Self {
@@ -96,53 +917,834 @@ impl Rules {
     }
 }
 
+impl std::str::FromStr for Rules {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_from_str(s)
+    }
+}
+
 impl fmt::Display for Rules {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Survival:")?;
-        for rule in &self.survive {
-            write!(f, " {}-{}", rule.0, rule.1)?;
+        writeln!(
+            f,
+            "Rule: {} ({})",
+            self.notation(Notation::Slash),
+            self.notation(Notation::Lettered)
+        )?;
+        if let Some(blocks) = &self.blocks {
+            write!(f, "Blocks: {} mappings", blocks.mappings())?;
+            if blocks.is_reversible() {
+                write!(f, ", reversible")?;
+            }
+            if blocks.conserves_cubes() {
+                write!(f, ", conserves cubes")?;
+            }
+            return write!(f, "\nExtra life: {}", self.life);
         }
-        write!(f, "\nSpawn:")?;
-        for rule in &self.spawn {
-            write!(f, " {}-{}", rule.0, rule.1)?;
+        if let Some(competition) = &self.competition {
+            return write!(f, "{competition}");
+        }
+        for (name, condition) in [("Survival", &self.survive), ("Spawn", &self.spawn)] {
+            write!(f, "{name}:")?;
+            if let Some(table) = &self.table {
+                let (survive, spawn) = table.classes();
+                let classes = if name == "Spawn" { spawn } else { survive };
+                writeln!(f, " {classes} configuration classes")?;
+                continue;
+            }
+            for count in Count::ALL {
+                let Some(ranges) = condition.ranges(count) else {
+                    continue;
+                };
+                if count != Count::Total {
+                    write!(f, " {count}")?;
+                }
+                for rule in ranges {
+                    write!(f, " {}-{}", rule.0, rule.1)?;
+                    if rule.2 != Chance::ALWAYS {
+                        write!(f, "@{}", rule.2)?;
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+        write!(f, "Extra life: {}", self.life)?;
+        if self.decay != Chance::NEVER {
+            write!(f, "\nDecay: {}", self.decay)?;
         }
-        write!(f, "\nExtra life: {}", self.life)?;
         write!(f, "\nNeighbourhood: {}", self.neighbourhood)?;
+        if let Some(direction) = self.direction() {
+            write!(f, "\nUp: {direction}")?;
+        }
+        write!(f, "\nSemantics: {}", self.semantics)?;
+        if self.heal > 0 {
+            write!(f, "\nHeal: {}", self.heal)?;
+        }
         Ok(())
     }
 }
 
-pub fn parse_condis(input: &str) -> Vec<(usize, usize)> {
-    let mut output = Vec::<(usize, usize)>::new();
-    for part in input.split(',') {
-        let x: Vec<_> = part.split('-').collect();
-        if x.len() == 1 {
-            match part.parse::<usize>() {
-                Ok(a) => output.push((a, a)),
-                _ => continue,
+// a named set of rules together with settings that suit them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub rules: &'static str,
+    pub semantics: Semantics,
+    pub density: f64,
+    pub minimum: f64,
+    pub maximum: f64,
+    // names of a color gradient and a light mode as accepted on the
+    // command line, e.g. "rainbow" and "bloom"
+    pub color: &'static str,
+    pub light: &'static str,
+}
+
+impl Preset {
+    pub fn rules(&self) -> Rules {
+        let mut rules = Rules::parse_from_str(self.rules).expect("invalid preset rules");
+        rules.set_semantics(self.semantics);
+        rules
+    }
+    // find a preset by name, neither case nor spaces, dashes or
+    // underscores matter, e.g. "crystal-growth" finds "Crystal Growth"
+    pub fn find(name: &str) -> Option<&'static Preset> {
+        let simplify = |s: &str| -> String {
+            s.chars()
+                .filter(|c| !matches!(c, ' ' | '-' | '_'))
+                .flat_map(|c| c.to_lowercase())
+                .collect()
+        };
+        PRESETS.iter().find(|p| simplify(p.name) == simplify(name))
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:<36} {:<8} density {:.3}, minimum {:.3}, maximum {:.3}, {} {}",
+            self.name,
+            self.rules,
+            self.semantics,
+            self.density,
+            self.minimum,
+            self.maximum,
+            self.color,
+            self.light
+        )
+    }
+}
+
+// the examples of the README are made with the legacy semantics, the
+// rules collected from the literature (see References in the README)
+// with the standard semantics
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "Default",
+        rules: "6-8/7/4/M",
+        semantics: Semantics::Legacy,
+        density: 0.1,
+        minimum: 0.025,
+        maximum: 0.25,
+        color: "black-white",
+        light: "normal",
+    },
+    Preset {
+        name: "Cloud Cycle",
+        rules: "5-6/5/3/M",
+        semantics: Semantics::Legacy,
+        density: 0.01,
+        minimum: 0.02,
+        maximum: 0.1,
+        color: "rainbow",
+        light: "bloom",
+    },
+    Preset {
+        name: "Slow Growth",
+        rules: "3,6/6/4/M",
+        semantics: Semantics::Legacy,
+        density: 0.1,
+        minimum: 0.05,
+        maximum: 0.25,
+        color: "rainbow",
+        light: "bloom",
+    },
+    // hit 'm' to start, see README
+    Preset {
+        name: "Oscillators",
+        rules: "3,6/6/4/M",
+        semantics: Semantics::Legacy,
+        density: 0.0,
+        minimum: 0.063,
+        maximum: 0.25,
+        color: "rainbow",
+        light: "bloom",
+    },
+    Preset {
+        name: "Growth Pattern",
+        rules: "5-10/6-7/4/M",
+        semantics: Semantics::Legacy,
+        density: 0.12,
+        minimum: 0.04,
+        maximum: 0.1,
+        color: "black-white",
+        light: "normal",
+    },
+    Preset {
+        name: "445",
+        rules: "4/4/5/M",
+        semantics: Semantics::Standard,
+        density: 0.05,
+        minimum: 0.0,
+        maximum: 0.5,
+        color: "petrol",
+        light: "normal",
+    },
+    Preset {
+        name: "Amoeba",
+        rules: "9-26/5-7,12-13,15/5/M",
+        semantics: Semantics::Standard,
+        density: 0.1,
+        minimum: 0.0,
+        maximum: 0.6,
+        color: "rainbow",
+        light: "normal",
+    },
+    Preset {
+        name: "Clouds 1",
+        rules: "13-26/13-14,17-19/2/M",
+        semantics: Semantics::Standard,
+        density: 0.5,
+        minimum: 0.0,
+        maximum: 1.0,
+        color: "black-white",
+        light: "normal",
+    },
+    Preset {
+        name: "Builder",
+        rules: "2,6,9/4,6,8-9/10/M",
+        semantics: Semantics::Standard,
+        density: 0.02,
+        minimum: 0.0,
+        maximum: 0.5,
+        color: "rainbow",
+        light: "bloom",
+    },
+    Preset {
+        name: "Crystal Growth",
+        rules: "0-6/1,3/2/VN",
+        semantics: Semantics::Standard,
+        density: 0.001,
+        minimum: 0.0,
+        maximum: 0.9,
+        color: "petrol",
+        light: "bloom",
+    },
+    Preset {
+        name: "Pyroclastic",
+        rules: "4-7/6-8/10/M",
+        semantics: Semantics::Standard,
+        density: 0.1,
+        minimum: 0.0,
+        maximum: 0.6,
+        color: "rainbow",
+        light: "bloom",
+    },
+    Preset {
+        name: "Slow Decay",
+        rules: "1,4,8,11,13-26/13-26/5/M",
+        semantics: Semantics::Standard,
+        density: 0.3,
+        minimum: 0.0,
+        maximum: 1.0,
+        color: "petrol",
+        light: "normal",
+    },
+];
+
+// ways to write down rules
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Notation {
+    /// survival/spawn/states/neighbourhood, e.g. "5-6/5/3/M"
+    #[default]
+    Slash,
+    /// birth/survival/states/neighbourhood as used by Golly and
+    /// LifeViewer, e.g. "B5/S5-6/C3/NM"
+    Lettered,
+}
+
+// rules written down in a specific notation
+pub struct RuleString<'a> {
+    rules: &'a Rules,
+    notation: Notation,
+}
+
+impl fmt::Display for RuleString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rules = self.rules;
+        let (survive, spawn) = (&rules.survive, &rules.spawn);
+        let states = match rules.decay {
+            Chance::NEVER => (rules.life + 2).to_string(),
+            decay => format!("{}@{decay}", rules.life + 2),
+        };
+        let neighbourhood = match (self.notation, &rules.neighbourhood) {
+            (_, Neighbourhood::FaceEdge) => "N18".to_string(),
+            (_, Neighbourhood::Custom(mask)) => format!("custom:{}", mask.source()),
+            (Notation::Slash, Neighbourhood::Moore(1)) => "M".to_string(),
+            (Notation::Slash, Neighbourhood::Moore(r)) => format!("M{r}"),
+            (Notation::Slash, Neighbourhood::VonNeumann(1)) => "VN".to_string(),
+            (Notation::Slash, Neighbourhood::VonNeumann(r)) => format!("VN{r}"),
+            (Notation::Lettered, Neighbourhood::Moore(1)) => "NM".to_string(),
+            (Notation::Lettered, Neighbourhood::Moore(r)) => format!("NM{r}"),
+            (Notation::Lettered, Neighbourhood::VonNeumann(1)) => "NN".to_string(),
+            (Notation::Lettered, Neighbourhood::VonNeumann(r)) => format!("NN{r}"),
+        };
+        if let Some(table) = &rules.table {
+            return write!(f, "table:{}", table.source());
+        }
+        if let Some(blocks) = &rules.blocks {
+            return write!(f, "margolus:{}", blocks.source());
+        }
+        if let Some(competition) = &rules.competition {
+            return write!(f, "{}", species::notation(competition, self.notation));
+        }
+        match (self.notation, rules.direction()) {
+            (Notation::Slash, None) => write!(f, "{survive}/{spawn}/{states}/{neighbourhood}"),
+            (Notation::Slash, Some(d)) => {
+                write!(f, "{survive}/{spawn}/{states}/{d}/{neighbourhood}")
             }
-        } else {
-            match (x[0].parse::<usize>(), x[1].parse::<usize>()) {
-                (Ok(a), Ok(b)) => output.push((a, b)),
-                _ => continue,
+            (Notation::Lettered, None) => {
+                write!(f, "B{spawn}/S{survive}/C{states}/{neighbourhood}")
             }
+            (Notation::Lettered, Some(d)) => {
+                write!(f, "B{spawn}/S{survive}/C{states}/D{d}/{neighbourhood}")
+            }
+        }
+    }
+}
+
+// section of a rule string, in the order of the slash notation
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Section {
+    Survival,
+    Spawn,
+    States,
+    Direction,
+    Neighbourhood,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Survival => write!(f, "survival"),
+            Section::Spawn => write!(f, "spawn"),
+            Section::States => write!(f, "states"),
+            Section::Direction => write!(f, "direction"),
+            Section::Neighbourhood => write!(f, "neighbourhood"),
+        }
+    }
+}
+
+// reasons why a rule string can not be parsed
+// positions are byte offsets into the rule string
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RuleParseError {
+    // there must be four or five sections separated by '/'
+    SectionCount {
+        found: usize,
+    },
+    EmptySection {
+        section: Section,
+        position: usize,
+    },
+    // a condition or the number of states is not a number, e.g. "5-x"
+    // or "3-"
+    InvalidNumber {
+        section: Section,
+        position: usize,
+        token: String,
+    },
+    // a probability like "5@1.5" or "4@x"
+    InvalidChance {
+        section: Section,
+        position: usize,
+        token: String,
+    },
+    // a range like "8-5"
+    InvertedRange {
+        section: Section,
+        position: usize,
+        start: usize,
+        end: usize,
+    },
+    // a neighbour count that can never be reached, e.g. 7 for the
+    // Von Neumann neighbourhood
+    CountTooLarge {
+        section: Section,
+        position: usize,
+        count: usize,
+        // which count the condition refers to, and its largest value
+        limit: (Count, usize),
+        neighbourhood: Neighbourhood,
+    },
+    StateCount {
+        position: usize,
+        states: isize,
+    },
+    UnknownNeighbourhood {
+        position: usize,
+        name: String,
+    },
+    // neither an axis nor a vector, e.g. "w" or "0,0,0"
+    InvalidDirection {
+        position: usize,
+        token: String,
+    },
+    // the counts of the parts of the neighbourhood can not be packed into
+    // a single neighbour count, see 'Rules::pack_count'
+    TooLargeToSplit {
+        neighbourhood: Neighbourhood,
+        split: Split,
+    },
+    // conditions refer to both directions and shapes of neighbours
+    MixedSplits,
+    // the file of isotropic non-totalistic rules can not be read or is
+    // malformed
+    Table {
+        message: String,
+    },
+    // the file of a block rule can not be read or is malformed
+    Blocks {
+        message: String,
+    },
+    // e.g. "M9"
+    ReachTooLarge {
+        position: usize,
+        reach: usize,
+    },
+    // the file of a custom neighbourhood can not be read or is malformed
+    Mask {
+        position: usize,
+        message: String,
+    },
+    // a section of the lettered notation that does not start with one of
+    // 'B', 'S', 'C' or 'N'
+    UnknownSection {
+        position: usize,
+        token: String,
+    },
+    DuplicateSection {
+        section: Section,
+        position: usize,
+    },
+    // the lettered notation needs both 'B' and 'S'
+    MissingSection {
+        section: Section,
+    },
+}
+
+impl RuleParseError {
+    // byte offset of the offending part of the rule string
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Self::SectionCount { .. }
+            | Self::MissingSection { .. }
+            | Self::TooLargeToSplit { .. }
+            | Self::MixedSplits
+            | Self::Table { .. }
+            | Self::Blocks { .. } => None,
+            Self::EmptySection { position, .. }
+            | Self::InvalidNumber { position, .. }
+            | Self::InvalidChance { position, .. }
+            | Self::InvertedRange { position, .. }
+            | Self::CountTooLarge { position, .. }
+            | Self::StateCount { position, .. }
+            | Self::UnknownNeighbourhood { position, .. }
+            | Self::InvalidDirection { position, .. }
+            | Self::ReachTooLarge { position, .. }
+            | Self::Mask { position, .. }
+            | Self::UnknownSection { position, .. }
+            | Self::DuplicateSection { position, .. } => Some(*position),
+        }
+    }
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SectionCount { found } => write!(
+                f,
+                "expected four sections like '5-6/5/3/M' (survival/spawn/states/neighbourhood), or five with a direction before the neighbourhood, found {found}"
+            )?,
+            Self::EmptySection { section, .. } => write!(f, "the {section} section is empty")?,
+            Self::InvalidNumber { section, token, .. } if token.trim().is_empty() => {
+                write!(f, "missing number in the {section} section")?
+            }
+            Self::InvalidNumber { section, token, .. } => {
+                write!(f, "'{token}' in the {section} section is not a number")?
+            }
+            Self::InvalidChance { section, token, .. } => write!(
+                f,
+                "'{token}' in the {section} section is not a probability between 0 and 1 with at most 6 decimal places"
+            )?,
+            Self::InvertedRange {
+                section,
+                start,
+                end,
+                ..
+            } => write!(
+                f,
+                "the range {start}-{end} in the {section} section is inverted, did you mean {end}-{start}?"
+            )?,
+            Self::CountTooLarge {
+                section,
+                count,
+                limit: (Count::Total, limit),
+                neighbourhood,
+                ..
+            } => write!(
+                f,
+                "{count} in the {section} section exceeds the largest count of the {neighbourhood} neighbourhood ({limit})"
+            )?,
+            Self::CountTooLarge {
+                section,
+                count,
+                limit: (counted, limit),
+                neighbourhood,
+                ..
+            } => write!(
+                f,
+                "{count} in the {section} section exceeds the largest count {} in the {neighbourhood} neighbourhood ({limit})",
+                counted.describe()
+            )?,
+            Self::StateCount { states, .. } => write!(
+                f,
+                "{states} states are not supported, there must be between 2 and {}",
+                MAX_LIFE + 2
+            )?,
+            Self::UnknownNeighbourhood { name, .. } => write!(
+                f,
+                "unknown neighbourhood '{name}', expected 'M' or 'NM' (Moore), 'VN' or 'NN' (Von Neumann), optionally followed by a range, 'N18' or 'custom:file'"
+            )?,
+            Self::InvalidDirection { token, .. } => write!(
+                f,
+                "invalid direction '{token}', expected an axis like 'y' or '-x', or a vector like '1,0,-1'"
+            )?,
+            Self::TooLargeToSplit {
+                neighbourhood,
+                split,
+            } => write!(
+                f,
+                "the {neighbourhood} neighbourhood is too large to count the neighbours {split} separately"
+            )?,
+            Self::MixedSplits => write!(
+                f,
+                "conditions may refer to the neighbours above, below and lateral to a cell, or to face, edge and corner neighbours, but not to both"
+            )?,
+            Self::Table { message } => {
+                write!(f, "invalid table of configuration classes, {message}")?
+            }
+            Self::Blocks { message } => write!(f, "invalid block rule, {message}")?,
+            Self::ReachTooLarge { reach, .. } => write!(
+                f,
+                "a range of {reach} is not supported, neighbourhoods may reach at most {MAX_REACH} cells"
+            )?,
+            Self::Mask { message, .. } => write!(f, "invalid custom neighbourhood, {message}")?,
+            Self::UnknownSection { token, .. } => write!(
+                f,
+                "unknown section '{token}', expected one starting with 'B', 'S', 'C', 'D' or 'N'"
+            )?,
+            Self::DuplicateSection { section, .. } => {
+                write!(f, "the {section} section appears more than once")?
+            }
+            Self::MissingSection { section } => write!(f, "the {section} section is missing")?,
+        }
+        if let Some(position) = self.position() {
+            write!(f, " (at position {})", position + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+// the number of states, optionally followed by the chance of decay,
+// e.g. "4@0.01"
+fn parse_states((part, position): (&str, usize)) -> Result<(isize, Chance), RuleParseError> {
+    let (part, decay) = match part.split_once('@') {
+        Some((states, decay)) => {
+            let at = position + states.len() + 1;
+            (states, parse_chance((decay, at), Section::States)?)
         }
+        None => (part, Chance::NEVER),
+    };
+    let states = part
+        .trim()
+        .parse::<isize>()
+        .map_err(|_| RuleParseError::InvalidNumber {
+            section: Section::States,
+            position,
+            token: part.to_string(),
+        })?;
+    if !(2..=MAX_LIFE + 2).contains(&states) {
+        return Err(RuleParseError::StateCount { position, states });
     }
-    output
+    Ok((states, decay))
 }
 
-fn check_exclusive(n: usize, condis: &[(usize, usize)]) -> bool {
-    let mut b = true;
-    for c in condis {
-        b = b && ((n < c.0) || (n > c.1));
+fn parse_chance(
+    (part, position): (&str, usize),
+    section: Section,
+) -> Result<Chance, RuleParseError> {
+    Chance::parse_from_str(part).ok_or_else(|| RuleParseError::InvalidChance {
+        section,
+        position,
+        token: part.to_string(),
+    })
+}
+
+fn parse_neighbourhood((part, position): (&str, usize)) -> Result<Neighbourhood, RuleParseError> {
+    if let Some(path) = part.trim().strip_prefix("custom:") {
+        return Mask::load(path)
+            .map(|mask| Neighbourhood::Custom(Arc::new(mask)))
+            .map_err(|message| RuleParseError::Mask { position, message });
+    }
+    let neighbourhood = Neighbourhood::parse_from_str(part.trim()).ok_or_else(|| {
+        RuleParseError::UnknownNeighbourhood {
+            position,
+            name: part.to_string(),
+        }
+    })?;
+    match neighbourhood.reach() {
+        reach if reach > MAX_REACH => Err(RuleParseError::ReachTooLarge { position, reach }),
+        _ => Ok(neighbourhood),
     }
-    b
 }
 
-fn check_inclusive(n: usize, condis: &[(usize, usize)]) -> bool {
-    let mut b = false;
-    for c in condis {
-        b = b || ((n >= c.0) && (n <= c.1));
+fn parse_direction((part, position): (&str, usize)) -> Result<Direction, RuleParseError> {
+    Direction::parse_from_str(part).ok_or_else(|| RuleParseError::InvalidDirection {
+        position,
+        token: part.to_string(),
+    })
+}
+
+// inverse of 'parse_condis' for a single count, every range repeats the
+// letter of the count
+fn format_condis(count: Count, condis: &[Range]) -> String {
+    condis
+        .iter()
+        .map(|&(a, b, chance)| {
+            let range = match a == b {
+                true => format!("{}{a}", count.prefix()),
+                false => format!("{}{a}-{b}", count.prefix()),
+            };
+            match chance {
+                Chance::ALWAYS => range,
+                chance => format!("{range}@{chance}"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// parse a comma separated list of neighbour counts and ranges like "3,5-7"
+// ranges of the counts above, below or lateral to the cell follow the
+// letters 'u', 'd' and 'l', e.g. "3,5-7,d1-9,u0", and ranges of the counts
+// of face, edge and corner neighbours follow 'f', 'e' and 'c' (see
+// 'Condition')
+// a letter only applies to the range it precedes, ranges without a letter
+// refer to the total count, e.g. "d1-9,4" means 1 to 9 neighbours below
+// and 4 in total, "d1-3,d5" means 1 to 3 or 5 below
+// a range may come with the chance of the outcome, e.g. "5@0.7"
+// an empty list is fine, e.g. cubes that never survive
+// 'offset' is the position of 'input' within the whole rule string
+// 'limits' are the largest possible counts, indexed by 'Count'
+pub fn parse_condis(
+    (input, offset): (&str, usize),
+    section: Section,
+    neighbourhood: &Neighbourhood,
+    limits: [usize; 7],
+) -> Result<Condition, RuleParseError> {
+    let mut output = Condition::default();
+    if input.trim().is_empty() {
+        return Ok(Condition::total(Vec::new()));
+    }
+    let mut position = offset;
+    for part in input.split(',') {
+        let number = |token: &str, position: usize| {
+            token
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| RuleParseError::InvalidNumber {
+                    section,
+                    position,
+                    token: token.to_string(),
+                })
+        };
+        let trimmed = part.trim_start();
+        let (count, body, at) = match Count::ALL[1..]
+            .iter()
+            .find(|c| trimmed.to_ascii_lowercase().starts_with(c.prefix()))
+        {
+            Some(&c) => {
+                let skipped = part.len() - trimmed.len() + 1;
+                (c, &part[skipped..], position + skipped)
+            }
+            None => (Count::Total, part, position),
+        };
+        let (body, chance) = match body.split_once('@') {
+            Some((range, chance)) => (
+                range,
+                parse_chance((chance, at + range.len() + 1), section)?,
+            ),
+            None => (body, Chance::ALWAYS),
+        };
+        let (a, b) = match body.split_once('-') {
+            None => {
+                let a = number(body, at)?;
+                (a, a)
+            }
+            Some((first, second)) => (number(first, at)?, number(second, at + first.len() + 1)?),
+        };
+        if a > b {
+            return Err(RuleParseError::InvertedRange {
+                section,
+                position: at,
+                start: a,
+                end: b,
+            });
+        }
+        if b > limits[count as usize] {
+            return Err(RuleParseError::CountTooLarge {
+                section,
+                position: at,
+                count: b,
+                limit: (count, limits[count as usize]),
+                neighbourhood: neighbourhood.clone(),
+            });
+        }
+        output.ranges[count as usize]
+            .get_or_insert_with(Vec::new)
+            .push((a, b, chance));
+        position += part.len() + 1;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Rules, RuleParseError> {
+        Rules::parse_from_str(input)
+    }
+
+    #[test]
+    fn slash_notation() {
+        let rules = parse("6-8/7/4/M").unwrap();
+        assert_eq!(rules.life(), 2);
+        assert_eq!(rules.neighbourhood(), &Neighbourhood::Moore(1));
+        let survive: Vec<bool> = (5..=9).map(|n| !rules.check_despawn(n)).collect();
+        assert_eq!(survive, [false, true, true, true, false]);
+        let spawn: Vec<bool> = (6..=8).map(|n| rules.check_spawn(n)).collect();
+        assert_eq!(spawn, [false, true, false]);
+        assert!(!rules.is_stochastic());
+        assert!(!rules.spawns_on_zero());
+    }
+
+    #[test]
+    fn lettered_notation() {
+        assert_eq!(parse("B7/S6-8/C4/NM"), parse("6-8/7/4/M"));
+        assert_eq!(parse("B1,3/S2/C2/NN"), parse("2/1,3/2/VN"));
+        let rules = parse("6-8/7/4/M").unwrap();
+        assert_eq!(rules.notation(Notation::Slash).to_string(), "6-8/7/4/M");
+        assert_eq!(
+            rules.notation(Notation::Lettered).to_string(),
+            "B7/S6-8/C4/NM"
+        );
+    }
+
+    #[test]
+    fn letters_apply_to_one_range() {
+        let neighbourhood = Neighbourhood::Moore(1);
+        let condition =
+            parse_condis(("d1-9,4", 0), Section::Survival, &neighbourhood, [26; 7]).unwrap();
+        assert_eq!(
+            condition.ranges(Count::Below),
+            Some(&[(1, 9, Chance::ALWAYS)][..])
+        );
+        assert_eq!(
+            condition.ranges(Count::Total),
+            Some(&[(4, 4, Chance::ALWAYS)][..])
+        );
+        let input = "4-6,d1-3,d5/u1-3,d0/4/y/M";
+        let rules = parse(input).unwrap();
+        assert_eq!(rules.notation(Notation::Slash).to_string(), input);
+    }
+
+    #[test]
+    fn slash_errors() {
+        assert_eq!(
+            parse("6-8/7/4"),
+            Err(RuleParseError::SectionCount { found: 3 })
+        );
+        assert!(matches!(
+            parse("6-8/7//M"),
+            Err(RuleParseError::EmptySection {
+                section: Section::States,
+                position: 6
+            })
+        ));
+        assert!(matches!(
+            parse("8-6/7/4/M"),
+            Err(RuleParseError::InvertedRange {
+                start: 8,
+                end: 6,
+                position: 0,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("6-x/7/4/M"),
+            Err(RuleParseError::InvalidNumber { position: 2, .. })
+        ));
+        assert!(matches!(
+            parse("6-8/7/1/M"),
+            Err(RuleParseError::StateCount {
+                states: 1,
+                position: 6
+            })
+        ));
+        assert!(matches!(
+            parse("6-8/7/4/X"),
+            Err(RuleParseError::UnknownNeighbourhood { position: 8, .. })
+        ));
+        assert!(matches!(
+            parse("2-7/1/4/VN"),
+            Err(RuleParseError::CountTooLarge { count: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn lettered_errors() {
+        assert!(matches!(
+            parse("B7/S6-8/B4/NM"),
+            Err(RuleParseError::DuplicateSection {
+                section: Section::Spawn,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("S6-8/C4/NM"),
+            Err(RuleParseError::MissingSection {
+                section: Section::Spawn
+            })
+        ));
+        assert!(matches!(
+            parse("B7/S6-8/X4/NM"),
+            Err(RuleParseError::UnknownSection { position: 8, .. })
+        ));
     }
-    b
 }
@@ -0,0 +1,648 @@
+use crate::margolus::{block_bit, BlockRule};
+use crate::rules::Rules;
+use crate::system::{
+    automaton_from_state, next_state, second_order_state, state_from_automaton, AutoSystem3d,
+    Automaton, Lookup, Noise, Occupancy, Scheme, StepReport, SystemDims,
+};
+#[cfg(feature = "render")]
+use bevy::prelude::Resource;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use xorwowgen::xorwow64::XorA;
+
+// edge length of a chunk in cells
+pub const CHUNK: usize = 16;
+pub(crate) const CHUNK_VOLUME: usize = CHUNK * CHUNK * CHUNK;
+
+// position of a cell, there are no walls so coordinates may be negative
+pub type Position = (isize, isize, isize);
+
+// position of a chunk, i.e. the position of its first cell divided by CHUNK
+pub type ChunkKey = (isize, isize, isize);
+
+// an unbounded system without any walls
+// only chunks of CHUNK x CHUNK x CHUNK cells that contain at least one
+// cube are kept in memory, new chunks are created as structures grow
+// into empty space and dropped once they are empty again
+// cells use the same state bytes as AutoSystem3d
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "render", derive(Resource))]
+pub struct SparseSystem3d {
+    chunks: HashMap<ChunkKey, Box<[u8]>>,
+    population: usize,
+    changes: Vec<(Position, u8)>,
+    scheme: Scheme,
+    // the generation before, only kept for second-order steps
+    previous: HashMap<ChunkKey, Box<[u8]>>,
+}
+
+fn split(ixyz: Position) -> (ChunkKey, usize) {
+    let c = CHUNK as isize;
+    let key = (
+        ixyz.0.div_euclid(c),
+        ixyz.1.div_euclid(c),
+        ixyz.2.div_euclid(c),
+    );
+    let local = (
+        ixyz.0.rem_euclid(c) as usize,
+        ixyz.1.rem_euclid(c) as usize,
+        ixyz.2.rem_euclid(c) as usize,
+    );
+    (key, (local.0 * CHUNK + local.1) * CHUNK + local.2)
+}
+
+fn join(key: ChunkKey, idx: usize) -> Position {
+    let c = CHUNK as isize;
+    (
+        key.0 * c + (idx / (CHUNK * CHUNK)) as isize,
+        key.1 * c + (idx / CHUNK % CHUNK) as isize,
+        key.2 * c + (idx % CHUNK) as isize,
+    )
+}
+
+// result of stepping a single chunk
+struct ChunkStep {
+    key: ChunkKey,
+    cells: Option<Box<[u8]>>,
+    changes: Vec<(Position, u8)>,
+    report: StepReport,
+}
+
+impl SparseSystem3d {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // copy all cubes of a bounded system, cell (0, 0, 0) keeps its position
+    pub fn from_dense(system: &AutoSystem3d) -> Self {
+        let mut sparse = Self::new();
+        let dims = system.dims();
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    if let Some(at) = system.get_at_xyz((i, j, k)) {
+                        sparse.set_xyz((i as isize, j as isize, k as isize), at);
+                    }
+                }
+            }
+        }
+        sparse.changes.clear();
+        sparse
+    }
+    // remove all cubes, the update scheme is kept
+    pub fn clear(&mut self) {
+        *self = Self {
+            scheme: self.scheme,
+            ..Self::new()
+        };
+    }
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.scheme = scheme;
+    }
+    pub fn get_at_xyz(&self, ixyz: Position) -> Option<Automaton> {
+        let (key, idx) = split(ixyz);
+        self.chunks
+            .get(&key)
+            .and_then(|cells| automaton_from_state(cells[idx]))
+    }
+    pub fn access_xyz(&mut self, ixyz: Position, value: Option<Automaton>) {
+        let (key, idx) = split(ixyz);
+        let new = state_from_automaton(value);
+        let old = match self.chunks.get_mut(&key) {
+            Some(cells) => std::mem::replace(&mut cells[idx], new),
+            None if new == 0 => 0,
+            None => {
+                let mut cells = vec![0u8; CHUNK_VOLUME].into_boxed_slice();
+                cells[idx] = new;
+                self.chunks.insert(key, cells);
+                0
+            }
+        };
+        match (old, new) {
+            (0, 0) => {}
+            (0, _) => self.population += 1,
+            (_, 0) => {
+                self.population -= 1;
+                if self.chunks[&key].iter().all(|&s| s == 0) {
+                    self.chunks.remove(&key);
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn set_xyz(&mut self, ixyz: Position, value: Automaton) {
+        self.access_xyz(ixyz, Some(value));
+    }
+    pub fn delete_xyz(&mut self, ixyz: Position) {
+        self.access_xyz(ixyz, None);
+    }
+    // number of occupied cells
+    pub fn population(&self) -> usize {
+        self.population
+    }
+    // number of chunks currently held in memory
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+    // smallest and largest coordinates of all cubes
+    pub fn bounding_box(&self) -> Option<(Position, Position)> {
+        let mut bounds: Option<(Position, Position)> = None;
+        for (key, cells) in &self.chunks {
+            for (idx, _) in cells.iter().enumerate().filter(|(_, &s)| s != 0) {
+                let p = join(*key, idx);
+                bounds = Some(match bounds {
+                    None => (p, p),
+                    Some((lo, hi)) => (
+                        (lo.0.min(p.0), lo.1.min(p.1), lo.2.min(p.2)),
+                        (hi.0.max(p.0), hi.1.max(p.1), hi.2.max(p.2)),
+                    ),
+                });
+            }
+        }
+        bounds
+    }
+    // all cubes, in no particular order
+    pub fn cells(&self) -> impl Iterator<Item = (Position, Automaton)> + '_ {
+        self.chunks.iter().flat_map(|(key, cells)| {
+            cells.iter().enumerate().filter_map(move |(idx, &s)| {
+                automaton_from_state(s).map(|at| (join(*key, idx), at))
+            })
+        })
+    }
+    // all cubes that have been changed by the last step
+    pub fn last_changes(&self) -> impl Iterator<Item = (Position, Option<Automaton>)> + '_ {
+        self.changes
+            .iter()
+            .map(|&(ixyz, state)| (ixyz, automaton_from_state(state)))
+    }
+    // advance the system by one generation
+    // there is no wall to jump back from, structures may grow in any
+    // direction, only empty cells next to at least one cube may spawn
+    // stochastic rules and update schemes draw their random numbers from
+    // 'noise', just like 'AutoSystem3d::step' does
+    // competing species are only supported by bounded systems
+    pub fn step(&mut self, rules: &Rules, growth: bool, noise: Noise) -> StepReport {
+        debug_assert!(rules.competition().is_none());
+        self.changes.clear();
+        if let Some(rule) = rules.blocks() {
+            let shifted = noise.generation() % 2 == 1;
+            let report = self.step_blocks(rules, rule, growth, shifted);
+            return report.with_population(self.population);
+        }
+        let report = match self.scheme {
+            Scheme::Synchronous | Scheme::Asynchronous(_) => {
+                self.step_half(rules, (growth, noise), 0, None)
+            }
+            // withholding spawns could not be undone
+            Scheme::SecondOrder => {
+                let before = std::mem::replace(&mut self.previous, self.chunks.clone());
+                self.step_half(rules, (true, noise), 0, Some(&before))
+            }
+            Scheme::Sequential => self.step_sequential(rules, growth, noise),
+            Scheme::Checkerboard => {
+                let first = self.step_half(rules, (growth, noise), 0, None);
+                first.merge(self.step_half(rules, (growth, noise), 1, None))
+            }
+        };
+        report.with_population(self.population)
+    }
+    // update all cells that are due during the given half of a step at
+    // once, see 'Scheme::updates'
+    // second-order steps take the generation before as 'before'
+    fn step_half(
+        &mut self,
+        rules: &Rules,
+        (growth, noise): (bool, Noise),
+        half: usize,
+        before: Option<&HashMap<ChunkKey, Box<[u8]>>>,
+    ) -> StepReport {
+        let neighbourhood = rules.counting();
+        let offsets = neighbourhood.offsets();
+        let weights = neighbourhood.weights();
+        let lookup = Lookup::new(rules, neighbourhood.max_count());
+        let occupancy = Occupancy::new(rules);
+        // every chunk that contains cubes, and all chunks next to them
+        // the reach of any neighbourhood is less than a chunk
+        // cubes of the generation before may reappear in second-order steps
+        let candidates: HashSet<ChunkKey> = self
+            .chunks
+            .keys()
+            .flat_map(|k| {
+                (-1..=1).flat_map(move |dx| {
+                    (-1..=1)
+                        .flat_map(move |dy| (-1..=1).map(move |dz| (k.0 + dx, k.1 + dy, k.2 + dz)))
+                })
+            })
+            .chain(before.into_iter().flat_map(|b| b.keys().copied()))
+            .collect();
+        let candidates: Vec<ChunkKey> = candidates.into_iter().collect();
+        let results: Vec<ChunkStep> = candidates
+            .par_iter()
+            .map(|&key| {
+                let counting = (&offsets[..], &weights[..]);
+                let before = before.and_then(|b| b.get(&key)).map(|c| &c[..]);
+                self.step_chunk(
+                    (key, before),
+                    counting,
+                    (&lookup, noise, half),
+                    occupancy,
+                    rules,
+                    growth,
+                )
+            })
+            .collect();
+        let mut report = StepReport::default();
+        for mut result in results {
+            match result.cells {
+                Some(cells) => {
+                    self.chunks.insert(result.key, cells);
+                }
+                None => {
+                    self.chunks.remove(&result.key);
+                }
+            }
+            self.changes.append(&mut result.changes);
+            report = report.merge(result.report);
+        }
+        self.population = self.population + report.births() - report.deaths();
+        report
+    }
+    // update the cells one by one in a random order, see
+    // 'AutoSystem3d::step', empty cells without any neighbours are skipped,
+    // as they cannot spawn anyway
+    fn step_sequential(&mut self, rules: &Rules, growth: bool, noise: Noise) -> StepReport {
+        let neighbourhood = rules.counting();
+        let offsets = neighbourhood.offsets();
+        let weights = neighbourhood.weights();
+        let lookup = Lookup::new(rules, neighbourhood.max_count());
+        let occupancy = Occupancy::new(rules);
+        // cells of every chunk that contains cubes and of all chunks next
+        // to them, smallest order first
+        // once a cube spawns, the chunks next to it are added, but only
+        // the cells that have not had their turn yet
+        let mut swept = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        let sweep = |key: ChunkKey,
+                     after: (u64, Position),
+                     swept: &mut HashSet<ChunkKey>,
+                     queue: &mut BinaryHeap<_>| {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let k = (key.0 + dx, key.1 + dy, key.2 + dz);
+                        if swept.insert(k) {
+                            let cells =
+                                (0..CHUNK_VOLUME).map(|idx| Scheme::order(join(k, idx), noise));
+                            queue.extend(cells.filter(|&o| o > after).map(Reverse));
+                        }
+                    }
+                }
+            }
+        };
+        let keys: Vec<ChunkKey> = self.chunks.keys().copied().collect();
+        for key in keys {
+            sweep(
+                key,
+                (0, (isize::MIN, isize::MIN, isize::MIN)),
+                &mut swept,
+                &mut queue,
+            );
+        }
+        let mut report = StepReport::default();
+        while let Some(Reverse(order)) = queue.pop() {
+            let ixyz = order.1;
+            let n: usize = offsets
+                .iter()
+                .zip(&weights)
+                .filter(|(o, _)| {
+                    occupancy.counts(self.state_at((ixyz.0 + o.0, ixyz.1 + o.1, ixyz.2 + o.2)))
+                })
+                .map(|(_, w)| w)
+                .sum();
+            let state = self.state_at(ixyz);
+            let (survive, spawn) = lookup.draw(n, ixyz, state != 0, noise);
+            let new = next_state(state, survive, spawn && growth && n > 0, rules);
+            if new != state {
+                self.access_xyz(ixyz, automaton_from_state(new));
+                report.count(state, new);
+                self.changes.push((ixyz, new));
+                if state == 0 {
+                    sweep(split(ixyz).0, order, &mut swept, &mut queue);
+                }
+            }
+        }
+        report
+    }
+    // replace every block of 2x2x2 cells that contains a cube by its new
+    // configuration, see 'AutoSystem3d::step'
+    // empty blocks stay empty, as they would have to be filled everywhere
+    fn step_blocks(
+        &mut self,
+        rules: &Rules,
+        rule: &BlockRule,
+        growth: bool,
+        shifted: bool,
+    ) -> StepReport {
+        let alive = state_from_automaton(Some(Automaton::new(rules.life())));
+        let start = |c: isize| c - (c + shifted as isize).rem_euclid(2);
+        let origins: HashSet<Position> = self
+            .cells()
+            .map(|(p, _)| (start(p.0), start(p.1), start(p.2)))
+            .collect();
+        let mut report = StepReport::default();
+        for origin in origins {
+            let cell = |b: usize| {
+                let (bx, by, bz) = (b >> 2 & 1, b >> 1 & 1, b & 1);
+                let p = (
+                    origin.0 + bx as isize,
+                    origin.1 + by as isize,
+                    origin.2 + bz as isize,
+                );
+                (p, block_bit(bx, by, bz))
+            };
+            let mut configuration = 0u8;
+            for b in 0..8 {
+                let (p, bit) = cell(b);
+                if self.state_at(p) != 0 {
+                    configuration |= 1 << bit;
+                }
+            }
+            let mut new = rule.apply(configuration);
+            if !growth && new.count_ones() > configuration.count_ones() {
+                new = configuration;
+            }
+            for b in 0..8 {
+                let (p, bit) = cell(b);
+                let state = self.state_at(p);
+                let next = match (state, new >> bit & 1 == 1) {
+                    (0, true) => alive,
+                    (_, false) => 0,
+                    (s, true) => s,
+                };
+                if next != state {
+                    self.access_xyz(p, automaton_from_state(next));
+                    report.count(state, next);
+                    self.changes.push((p, next));
+                }
+            }
+        }
+        report
+    }
+    // swap the current and the previous generation, so that second-order
+    // steps run backwards in time, see 'AutoSystem3d::reverse'
+    pub fn reverse(&mut self) -> StepReport {
+        std::mem::swap(&mut self.chunks, &mut self.previous);
+        self.changes.clear();
+        let keys: HashSet<ChunkKey> = self
+            .chunks
+            .keys()
+            .chain(self.previous.keys())
+            .copied()
+            .collect();
+        let mut report = StepReport::default();
+        for key in keys {
+            for idx in 0..CHUNK_VOLUME {
+                let old = self.previous.get(&key).map_or(0, |c| c[idx]);
+                let new = self.chunks.get(&key).map_or(0, |c| c[idx]);
+                if old != new {
+                    report.count(old, new);
+                    self.changes.push((join(key, idx), new));
+                }
+            }
+        }
+        self.population = self.population + report.births() - report.deaths();
+        report.with_population(self.population)
+    }
+    fn state_at(&self, ixyz: Position) -> u8 {
+        let (key, idx) = split(ixyz);
+        self.chunks.get(&key).map_or(0, |cells| cells[idx])
+    }
+    fn step_chunk(
+        &self,
+        (key, before): (ChunkKey, Option<&[u8]>),
+        (offsets, weights): (&[Position], &[usize]),
+        (lookup, noise, half): (&Lookup, Noise, usize),
+        occupancy: Occupancy,
+        rules: &Rules,
+        growth: bool,
+    ) -> ChunkStep {
+        // cells that count as neighbours within the chunk and a margin of
+        // one chunk around it
+        const PADDED: usize = 3 * CHUNK;
+        let mut occupied = vec![false; PADDED * PADDED * PADDED];
+        for dx in 0..3 {
+            for dy in 0..3 {
+                for dz in 0..3 {
+                    let k = (key.0 + dx - 1, key.1 + dy - 1, key.2 + dz - 1);
+                    if let Some(cells) = self.chunks.get(&k) {
+                        for (idx, _) in cells
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, &s)| occupancy.counts(s))
+                        {
+                            let (x, y, z) =
+                                (idx / (CHUNK * CHUNK), idx / CHUNK % CHUNK, idx % CHUNK);
+                            let p = (
+                                dx as usize * CHUNK + x,
+                                dy as usize * CHUNK + y,
+                                dz as usize * CHUNK + z,
+                            );
+                            occupied[(p.0 * PADDED + p.1) * PADDED + p.2] = true;
+                        }
+                    }
+                }
+            }
+        }
+        let deltas: Vec<isize> = offsets
+            .iter()
+            .map(|o| (o.0 * PADDED as isize + o.1) * PADDED as isize + o.2)
+            .collect();
+        let current = self.chunks.get(&key);
+        let mut cells = vec![0u8; CHUNK_VOLUME].into_boxed_slice();
+        let mut changes = Vec::new();
+        let mut report = StepReport::default();
+        for (idx, cell) in cells.iter_mut().enumerate() {
+            let (x, y, z) = (idx / (CHUNK * CHUNK), idx / CHUNK % CHUNK, idx % CHUNK);
+            let p = (((x + CHUNK) * PADDED + y + CHUNK) * PADDED + z + CHUNK) as isize;
+            let n: usize = deltas
+                .iter()
+                .zip(weights)
+                .filter(|(&d, _)| occupied[(p + d) as usize])
+                .map(|(_, w)| w)
+                .sum();
+            let state = current.map_or(0, |c| c[idx]);
+            let ixyz = join(key, idx);
+            let (survive, spawn) = lookup.draw(n, ixyz, state != 0, noise);
+            // empty space far away from any cube stays empty
+            let spawn = spawn && growth && n > 0;
+            *cell = match self.scheme {
+                Scheme::SecondOrder => {
+                    let before = before.map_or(0, |c| c[idx]);
+                    second_order_state(state, before, survive, spawn, rules)
+                }
+                _ if self.scheme.updates(ixyz, noise, half) => {
+                    next_state(state, survive, spawn, rules)
+                }
+                _ => state,
+            };
+            if *cell != state {
+                report.count(state, *cell);
+                changes.push((join(key, idx), *cell));
+            }
+        }
+        ChunkStep {
+            key,
+            cells: cells.iter().any(|&s| s != 0).then_some(cells),
+            changes,
+            report,
+        }
+    }
+    // populate the box spanned by 'dims' pseudorandomly, using the same
+    // generators as 'AutoSystem3d::fill_pseudorandom'
+    pub fn fill_pseudorandom(
+        &mut self,
+        dims: &SystemDims,
+        density: f64,
+        life: isize,
+        seed: u64,
+    ) -> Vec<Position> {
+        let mut spawned = Vec::new();
+        for i in dims.range_x() {
+            let mut rng = XorA::seed_from_u64(seed.wrapping_add(i.wrapping_mul(999999999) as u64));
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    let ixyz = (i as isize, j as isize, k as isize);
+                    if rng.gen_bool(density) && self.get_at_xyz(ixyz).is_none() {
+                        self.set_xyz(ixyz, Automaton::new(life));
+                        spawned.push(ixyz);
+                    }
+                }
+            }
+        }
+        spawned
+    }
+    // populate the center of the box spanned by 'dims' pseudorandomly
+    pub fn fill_pseudorandom_core<R: Rng>(
+        &mut self,
+        dims: &SystemDims,
+        fract: usize,
+        density: f64,
+        life: isize,
+        rng: &mut R,
+    ) -> Vec<Position> {
+        let mut spawned = Vec::new();
+        for i in dims.core_range_x(fract) {
+            for j in dims.core_range_y(fract) {
+                for k in dims.core_range_z(fract) {
+                    let ixyz = (i as isize, j as isize, k as isize);
+                    if rng.gen_bool(density) && self.get_at_xyz(ixyz).is_none() {
+                        self.set_xyz(ixyz, Automaton::new(life));
+                        spawned.push(ixyz);
+                    }
+                }
+            }
+        }
+        spawned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::{Boundaries, Boundary};
+
+    // a pseudorandom cube of 8 x 8 x 8 cells in the middle of a 40 x 40 x 40
+    // system with dead walls
+    fn seeded(rules: &Rules, scheme: Scheme) -> AutoSystem3d {
+        let dims = SystemDims::new(40, 40, 40);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.set_boundaries(Boundaries::all(Boundary::Dead));
+        system.set_scheme(scheme);
+        let mut rng = XorA::seed_from_u64(5);
+        for i in 16..24 {
+            for j in 16..24 {
+                for k in 16..24 {
+                    if rng.gen_bool(0.4) {
+                        system.set_xyz((i, j, k), Automaton::new(rules.life()));
+                    }
+                }
+            }
+        }
+        system
+    }
+
+    // the cubes of a bounded system, which must not touch the walls
+    fn dense_cells(system: &AutoSystem3d) -> Vec<(Position, Automaton)> {
+        let dims = system.dims();
+        let mut cells = Vec::new();
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    if let Some(at) = system.get_at_xyz((i, j, k)) {
+                        let ixyz = (i as isize, j as isize, k as isize);
+                        let edge = dims.x() as isize - 1;
+                        assert!([ixyz.0, ixyz.1, ixyz.2]
+                            .iter()
+                            .all(|c| (1..edge).contains(c)));
+                        cells.push((ixyz, at));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    fn sparse_cells(system: &SparseSystem3d) -> Vec<(Position, Automaton)> {
+        let mut cells: Vec<_> = system.cells().collect();
+        cells.sort_by_key(|c| c.0);
+        cells
+    }
+
+    #[test]
+    fn matches_bounded_system() {
+        let rules = Rules::parse_from_str("6-8/7/4/M").unwrap();
+        for scheme in [Scheme::Synchronous, Scheme::Checkerboard] {
+            let mut dense = seeded(&rules, scheme);
+            let dims = dense.dims();
+            let mut sparse = SparseSystem3d::from_dense(&dense);
+            sparse.set_scheme(scheme);
+            for g in 0..16 {
+                let noise = Noise::new(0, g);
+                let expected = dense.step(&rules, &dims, true, noise);
+                let report = sparse.step(&rules, true, noise);
+                assert_eq!(report, expected, "{scheme} generation {g}");
+                assert_eq!(
+                    sparse_cells(&sparse),
+                    dense_cells(&dense),
+                    "{scheme} generation {g}"
+                );
+                assert_eq!(sparse.last_changes().count(), dense.last_changes().count());
+            }
+        }
+    }
+
+    #[test]
+    fn emptied_chunks_are_removed() {
+        let mut system = SparseSystem3d::new();
+        // the corner of a chunk, its neighbours fill 8 chunks, and a cube
+        // whose neighbours fit into its own chunk
+        system.set_xyz((15, 15, 15), Automaton::new(0));
+        system.set_xyz((100, 8, 8), Automaton::new(0));
+        assert_eq!(system.chunk_count(), 2);
+        let rules = Rules::parse_from_str("1/1/2/M").unwrap();
+        system.step(&rules, true, Noise::default());
+        assert_eq!(system.population(), 2 * 26);
+        assert_eq!(system.chunk_count(), 8 + 1);
+        // the shells die, only the cube in the middle of the second one
+        // survives
+        system.set_xyz((100, 8, 8), Automaton::new(0));
+        let rules = Rules::parse_from_str("26/26/2/M").unwrap();
+        system.step(&rules, false, Noise::default());
+        assert_eq!(system.population(), 1);
+        assert_eq!(system.chunks.keys().collect::<Vec<_>>(), [&(6, 0, 0)]);
+    }
+}
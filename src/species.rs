@@ -0,0 +1,239 @@
+use crate::rules::{Notation, Rules, Semantics};
+use crate::system::{Automaton, SystemDims};
+use std::fmt;
+
+// highest number of species, every cube stores its species in a byte
+pub const MAX_SPECIES: usize = u8::MAX as usize + 1;
+
+// several species of cubes share the system, every cube belongs to one of
+// them, and every species follows its own rules, i.e. its own survival
+// and spawn ranges, life and neighbourhood
+// the cubes of a species within its neighbourhood are the parents of a
+// new cube: an empty cell is claimed by a species if its spawn condition
+// applies and the cell has at least one parent of this species, if
+// several species qualify, 'Ownership' decides
+// species are numbered from 0 in the order they are given
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Competition {
+    species: Vec<Rules>,
+    neighbours: Neighbours,
+    ownership: Ownership,
+}
+
+impl Competition {
+    pub fn new(species: Vec<Rules>, neighbours: Neighbours, ownership: Ownership) -> Self {
+        assert!(
+            (1..=MAX_SPECIES).contains(&species.len()),
+            "between 1 and {MAX_SPECIES} species can compete"
+        );
+        Self {
+            species,
+            neighbours,
+            ownership,
+        }
+    }
+    pub fn species(&self) -> &[Rules] {
+        &self.species
+    }
+    pub fn neighbours(&self) -> Neighbours {
+        self.neighbours
+    }
+    pub fn ownership(&self) -> Ownership {
+        self.ownership
+    }
+    // rules of the given species
+    pub fn rules(&self, species: u8) -> &Rules {
+        &self.species[species as usize]
+    }
+    // highest life of all species
+    pub fn life(&self) -> isize {
+        self.species.iter().map(|r| r.life()).max().unwrap_or(0)
+    }
+    pub(crate) fn set_semantics(&mut self, semantics: Semantics) {
+        for rules in &mut self.species {
+            rules.set_semantics(semantics);
+        }
+    }
+    pub(crate) fn set_heal(&mut self, heal: isize) {
+        for rules in &mut self.species {
+            rules.set_heal(heal);
+        }
+    }
+    // the species initially seeded at slice x = i: the system is split
+    // along the x axis into equal regions, one per species
+    pub fn region(&self, i: usize, dims: &SystemDims) -> u8 {
+        (i * self.species.len() / dims.x().max(1)).min(self.species.len() - 1) as u8
+    }
+    // a new cube with full life of the species seeded at slice x = i
+    pub fn seed(&self, i: usize, dims: &SystemDims) -> Automaton {
+        let species = self.region(i, dims);
+        Automaton::new(self.rules(species).life()).with_species(species)
+    }
+}
+
+impl fmt::Display for Competition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (s, rules) in self.species.iter().enumerate() {
+            writeln!(f, "Species {}:", s + 1)?;
+            for line in rules.to_string().lines() {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        write!(
+            f,
+            "Neighbours: {}\nOwnership: {}",
+            self.neighbours, self.ownership
+        )
+    }
+}
+
+// the rule strings of all species, separated by commas
+pub(crate) fn notation(competition: &Competition, notation: Notation) -> String {
+    competition
+        .species
+        .iter()
+        .map(|r| r.notation(notation).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// neighbours the rules of a species are applied to
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(clap::ValueEnum))]
+pub enum Neighbours {
+    /// only cubes of the same species count, the species do not notice
+    /// each other apart from competing for empty cells
+    #[default]
+    Own,
+    /// cubes of every species count, so crowding by other species
+    /// may kill a cube
+    All,
+}
+
+impl fmt::Display for Neighbours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Neighbours::Own => write!(f, "own"),
+            Neighbours::All => write!(f, "all"),
+        }
+    }
+}
+
+// the species a new cube belongs to if several species could spawn it
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(clap::ValueEnum))]
+pub enum Ownership {
+    /// the species with the most parents, ties are broken at random
+    #[default]
+    Majority,
+    /// a random parent, i.e. every species with the chance of its share
+    /// of the parents
+    Random,
+}
+
+impl Ownership {
+    // pick the owner from the candidates, i.e. species and their number of
+    // parents, none of which is 0, 'random' is a random number of the cell
+    pub(crate) fn pick(&self, candidates: &[(u8, usize)], random: u64) -> u8 {
+        match self {
+            Ownership::Majority => {
+                let most = candidates.iter().map(|c| c.1).max().unwrap_or(0);
+                let tied: Vec<u8> = candidates
+                    .iter()
+                    .filter(|c| c.1 == most)
+                    .map(|c| c.0)
+                    .collect();
+                tied[(random % tied.len() as u64) as usize]
+            }
+            Ownership::Random => {
+                let total: usize = candidates.iter().map(|c| c.1).sum();
+                let mut parent = (random % total as u64) as usize;
+                for &(species, parents) in candidates {
+                    if parent < parents {
+                        return species;
+                    }
+                    parent -= parents;
+                }
+                unreachable!("every parent belongs to a candidate")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ownership::Majority => write!(f, "majority"),
+            Ownership::Random => write!(f, "random"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{AutoSystem3d, Noise};
+
+    #[test]
+    fn majority() {
+        let majority = Ownership::Majority;
+        // a single species with the most parents always wins
+        for random in 0..4 {
+            assert_eq!(majority.pick(&[(0, 2), (1, 7), (2, 6)], random), 1);
+        }
+        // ties are broken by the random number, other species never win
+        let tied = [(0, 3), (1, 5), (2, 5)];
+        assert_eq!(majority.pick(&tied, 0), 1);
+        assert_eq!(majority.pick(&tied, 1), 2);
+        assert_eq!(majority.pick(&tied, 2), 1);
+        assert_eq!(majority.pick(&tied, u64::MAX), 2);
+        assert_eq!(majority.pick(&[(4, 1)], 7), 4);
+    }
+
+    #[test]
+    fn random() {
+        let random = Ownership::Random;
+        // every parent stands for its species
+        let candidates = [(2, 2), (5, 1), (1, 3)];
+        let picks: Vec<u8> = (0..6).map(|r| random.pick(&candidates, r)).collect();
+        assert_eq!(picks, [2, 2, 5, 1, 1, 1]);
+        assert_eq!(random.pick(&candidates, 6), 2);
+        assert_eq!(random.pick(&candidates, 8), 5);
+        assert_eq!(random.pick(&[(0, 1), (1, 3)], 4), 0);
+        assert_eq!(random.pick(&[(0, 1), (1, 3)], 7), 1);
+    }
+
+    // two rows of cubes side by side, all but one of them would die if
+    // they counted the cubes of the other species
+    #[test]
+    fn own_neighbours() {
+        let dims = SystemDims::new(8, 8, 8);
+        let species = vec![
+            Rules::parse_from_str("1/26/2/M").unwrap(),
+            Rules::parse_from_str("1-2/26/3/M").unwrap(),
+        ];
+        for (neighbours, decays) in [(Neighbours::Own, 0), (Neighbours::All, 4)] {
+            let competition = Competition::new(species.clone(), neighbours, Ownership::Majority);
+            let rules = Rules::competing(competition.clone());
+            let mut system = AutoSystem3d::new_from_dims(&dims);
+            for k in 2..4 {
+                system.set_xyz((2, 2, k), Automaton::new(2));
+            }
+            for k in 2..5 {
+                system.set_xyz((3, 2, k), Automaton::new(3).with_species(1));
+            }
+            let report = system.step(&rules, &dims, true, Noise::default());
+            assert_eq!(report.decays(), decays, "{neighbours}");
+            assert_eq!(report.births(), 0);
+            if neighbours == Neighbours::Own {
+                for k in 2..4 {
+                    assert_eq!(system.get_at_xyz((2, 2, k)), Some(Automaton::new(2)));
+                }
+                for k in 2..5 {
+                    let cube = Automaton::new(3).with_species(1);
+                    assert_eq!(system.get_at_xyz((3, 2, k)), Some(cube));
+                }
+            }
+        }
+    }
+}
@@ -1,33 +1,54 @@
-use bevy::prelude::{Entity, Resource};
+use crate::bitgrid::{count_set, BitGrid};
+use crate::blocks::{Blocks, BLOCK};
+use crate::boundary::{Boundaries, Boundary, Neighbour};
+use crate::chunks::{Chunks, Column};
+use crate::margolus::{block_bit, BlockRule};
+use crate::rules::{Chance, Neighbourhood, Rules, Semantics};
+use crate::sparse::CHUNK;
+use crate::species::{Competition, Neighbours};
+use crate::{isizify3, MAX_EDGE};
+#[cfg(feature = "render")]
+use bevy::prelude::Resource;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use xorwowgen::xorwow64::XorA;
 
+// a cube with the given amount of life, and the species it belongs to
+// if several species compete, see 'Competition'
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Automaton {
-    entity: Entity,
     life: isize,
+    species: u8,
 }
 
 impl Automaton {
-    pub fn new(entity: Entity, life: isize) -> Self {
-        Self { entity, life }
+    pub fn new(life: isize) -> Self {
+        Self { life, species: 0 }
     }
-    pub fn new_zero(entity: Entity) -> Self {
+    pub fn new_zero() -> Self {
         Self {
-            entity,
             life: 0isize,
+            species: 0,
         }
     }
+    pub fn with_species(self, species: u8) -> Self {
+        Self { species, ..self }
+    }
     pub fn change_life(&mut self, step: isize) {
         self.life += step;
     }
     pub fn life(&self) -> isize {
         self.life
     }
-    pub fn entity(&self) -> Entity {
-        self.entity
+    pub fn species(&self) -> u8 {
+        self.species
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Resource)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub struct SystemDims {
     x: usize,
     y: usize,
@@ -46,7 +67,7 @@ impl SystemDims {
         Self { x, y, z }
     }
     pub fn new_cube_clamped(min: usize, max: usize, value: usize) -> Self {
-        let l = value.max(min).min(max);
+        let l = value.clamp(min, max);
         Self { x: l, y: l, z: l }
     }
     pub fn x(&self) -> usize {
@@ -68,19 +89,58 @@ impl SystemDims {
         create_range(self.z)
     }
     pub fn core_range_x(&self, fract: usize) -> std::ops::Range<usize> {
-        fract_range(self.x, fract)
+        fract_range(self.x, self.core_edge(fract))
     }
     pub fn core_range_y(&self, fract: usize) -> std::ops::Range<usize> {
-        fract_range(self.y, fract)
+        fract_range(self.y, self.core_edge(fract))
     }
     pub fn core_range_z(&self, fract: usize) -> std::ops::Range<usize> {
-        fract_range(self.z, fract)
+        fract_range(self.z, self.core_edge(fract))
+    }
+    // edge length of the center, which is a cube whose volume is about
+    // 1 / fract^3 of the volume of the system, see 'fract_range'
+    fn core_edge(&self, fract: usize) -> usize {
+        cube_root(self.max_amount()) / fract + 1
     }
     pub fn max_amount(&self) -> usize {
         self.x * self.y * self.z
     }
 }
 
+// parse dimensions like "128x128x8", every length lies between 1 and
+// MAX_EDGE
+impl std::str::FromStr for SystemDims {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let axes: Vec<&str> = s.split(['x', 'X']).collect();
+        let [x, y, z] = axes[..] else {
+            return Err(format!(
+                "expected three lengths like '128x128x8', got '{s}'"
+            ));
+        };
+        let parse = |a: &str| match a.trim().parse::<usize>() {
+            Ok(0) => Err("lengths must be at least 1".to_string()),
+            Ok(l) if l > MAX_EDGE => Err(format!("lengths may be at most {MAX_EDGE}, got {l}")),
+            Ok(l) => Ok(l),
+            Err(e) => Err(format!("invalid length '{a}': {e}")),
+        };
+        Ok(Self::new(parse(x)?, parse(y)?, parse(z)?))
+    }
+}
+
+// largest integer whose cube does not exceed 'v'
+fn cube_root(v: usize) -> usize {
+    let mut r = (v as f64).cbrt().round() as usize;
+    while r.pow(3) > v {
+        r -= 1;
+    }
+    while (r + 1).pow(3) <= v {
+        r += 1;
+    }
+    r
+}
+
 fn create_range(x: usize) -> std::ops::Range<usize> {
     std::ops::Range::<usize> {
         start: 0usize,
@@ -88,24 +148,47 @@ fn create_range(x: usize) -> std::ops::Range<usize> {
     }
 }
 
-fn fract_range(x: usize, fract: usize) -> std::ops::Range<usize> {
-    let part = x / fract + 1;
+// range of length 'part' in the middle of an axis of length 'x'
+// the range is cut off if the axis is shorter than that
+fn fract_range(x: usize, part: usize) -> std::ops::Range<usize> {
+    let part = part.min(x);
     let start = x / 2 - part / 2;
     let end = start + part;
     std::ops::Range::<usize> { start, end }
 }
 
-// contains a three dimensional vector of Option<Automaton>
+// contains the state of every cell in a single contiguous buffer
+// a second buffer of the same size keeps the previous generation and
+// receives the new state during a step
 // the minimum number of possible states is two:
 // Some(Automaton) and None
-#[derive(Debug, Clone, Resource)]
+// each cell is stored as one byte, 0 represents None, any other
+// value represents Some(Automaton) with 'life + 1'
+// cells are kept in chunks, which are only allocated where there are
+// cubes, see 'Chunks'
+// the species of every cube is kept in separate chunks of the same
+// layout, which are only allocated where there are cubes of a species
+// other than the first one, see 'Competition'
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "render", derive(Resource))]
 pub struct AutoSystem3d {
-    data: Vec<Vec<Vec<Option<Automaton>>>>,
+    data: Chunks<u8>,
+    previous: Chunks<u8>,
+    species: Chunks<u8>,
+    dims: SystemDims,
+    population: usize,
+    stepping: Stepping,
+    scheme: Scheme,
+    blocks: Blocks,
+    last_step: Option<(Rules, bool)>,
+    count_field: Option<CountField>,
+    boundaries: Boundaries,
 }
-// defines how to deal with the borders of the system
+// periodic borders, regardless of the boundaries of the system
 // trying to access position -1,0,64 in a 64x64x64
 // system will result in accessing position 63,0,0
 // so we actually jump back to the opposite wall
+// see 'Boundaries' for the other ways to deal with the borders
 fn rem_euclid_3d(ixyz: (isize, isize, isize), dims: &SystemDims) -> (usize, usize, usize) {
     (
         ixyz.0.rem_euclid(dims.x as isize) as usize,
@@ -114,107 +197,1426 @@ fn rem_euclid_3d(ixyz: (isize, isize, isize), dims: &SystemDims) -> (usize, usiz
     )
 }
 
+// defines how neighbours are counted during a step
+// all of them produce identical results
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "render", derive(clap::ValueEnum))]
+pub enum Stepping {
+    /// count the neighbours of each cell one by one
+    Cellwise,
+    /// count the neighbours of 64 cells at once using packed bit rows
+    #[default]
+    Bitwise,
+    /// keep the neighbour count of each cell, update it with every
+    /// birth and death
+    Incremental,
+}
+
+// the order in which cells are updated during a step
+// apart from 'Synchronous', the schemes draw their random numbers from
+// the 'Noise' of a step, so their results are reproducible as well
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Scheme {
+    // all cells are updated at once, reading the previous generation
+    #[default]
+    Synchronous,
+    // cells are updated one after another in a random order, every cell
+    // reads the state left by the cells before it
+    Sequential,
+    // every cell is updated with the given chance, reading the previous
+    // generation, the others keep their state
+    Asynchronous(Chance),
+    // cells with an even sum of coordinates are updated first, then the
+    // remaining cells read their new state
+    Checkerboard,
+    // a cell is occupied if the rules would occupy it in the next
+    // generation, or if it was occupied in the previous one, but not both
+    // this can be undone by swapping both generations, see
+    // 'AutoSystem3d::reverse', cubes are either empty or fully alive,
+    // and the spawn conditions always apply
+    SecondOrder,
+}
+
+impl Scheme {
+    // whether the cell at 'ixyz' is updated during the given half of a
+    // step, only checkerboard updates take two halves
+    pub(crate) fn updates(&self, ixyz: (isize, isize, isize), noise: Noise, half: usize) -> bool {
+        match self {
+            Scheme::Asynchronous(alpha) => alpha.occurs(noise.random(ixyz, 3)),
+            Scheme::Checkerboard => (ixyz.0 + ixyz.1 + ixyz.2).rem_euclid(2) as usize == half,
+            Scheme::Synchronous | Scheme::Sequential | Scheme::SecondOrder => true,
+        }
+    }
+    // position of the cell at 'ixyz' within the random order of a
+    // sequential step, ties are broken by the position
+    pub(crate) fn order(ixyz: (isize, isize, isize), noise: Noise) -> (u64, (isize, isize, isize)) {
+        (noise.random(ixyz, 4), ixyz)
+    }
+}
+
+impl std::str::FromStr for Scheme {
+    type Err = String;
+
+    // "synchronous", "sequential", "checkerboard", "second-order" or
+    // "asynchronous", the latter optionally with the chance of a cell to
+    // be updated, e.g. "asynchronous=0.3", which defaults to 0.5
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, alpha) = match s.split_once('=') {
+            Some((name, alpha)) => (name, Some(alpha)),
+            None => (s, None),
+        };
+        match (name.trim(), alpha) {
+            ("synchronous", None) => Ok(Scheme::Synchronous),
+            ("sequential", None) => Ok(Scheme::Sequential),
+            ("checkerboard", None) => Ok(Scheme::Checkerboard),
+            ("second-order", None) => Ok(Scheme::SecondOrder),
+            ("asynchronous", None) => Ok(Scheme::Asynchronous(Chance::HALF)),
+            ("asynchronous", Some(alpha)) => Chance::parse_from_str(alpha)
+                .map(Scheme::Asynchronous)
+                .ok_or_else(|| {
+                    format!("'{alpha}' is not a probability between 0 and 1 with at most 6 decimal places")
+                }),
+            _ => Err(format!(
+                "unknown update scheme '{s}', expected one of synchronous, sequential, asynchronous, asynchronous=<chance>, checkerboard, second-order"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Scheme::Synchronous => write!(f, "synchronous"),
+            Scheme::Sequential => write!(f, "sequential"),
+            Scheme::Asynchronous(alpha) => write!(f, "asynchronous={alpha}"),
+            Scheme::Checkerboard => write!(f, "checkerboard"),
+            Scheme::SecondOrder => write!(f, "second-order"),
+        }
+    }
+}
+
+// precomputed data needed by the chosen stepping
+enum Counting {
+    Cellwise,
+    // packed occupancy, sets of survival and spawn counts, unless they
+    // are too large, see 'count_set'
+    Bitwise(BitGrid, Option<Vec<usize>>, Option<Vec<usize>>),
+    // see 'CountField'
+    Incremental,
+}
+
+// thread local state of a step
+// 'cells' is the column of chunks of the new state the thread writes to
+// 'active' marks the blocks that need to be evaluated, None means all
+struct Slab<'a> {
+    rules: &'a Rules,
+    lookup: &'a Lookup,
+    growth: bool,
+    noise: Noise,
+    scheme: Scheme,
+    // the half of a checkerboard step, see 'Scheme::updates'
+    half: usize,
+    cells: Column<'a, u8>,
+    active: Option<&'a [bool]>,
+    recorder: &'a [AtomicBool],
+    report: StepReport,
+}
+
+// new state of a single cell
+// survive: the neighbour count is within the survival range
+// spawn: the neighbour count is within the spawn range, and growth is allowed
+pub(crate) fn next_state(state: u8, survive: bool, spawn: bool, rules: &Rules) -> u8 {
+    let alive = state_from_automaton(Some(Automaton::new(rules.life())));
+    // a weakened cube within the survival range regains life
+    let healed = |s: u8| (s as isize + rules.heal()).min(alive as isize) as u8;
+    if rules.semantics() == Semantics::Standard {
+        return match state {
+            0 if spawn => alive,
+            0 => 0,
+            s if s == alive && survive => s,
+            s if survive && rules.heal() > 0 => healed(s),
+            // once a cube loses life, it keeps decaying until it is gone
+            s => s - 1,
+        };
+    }
+    if !survive {
+        // despawn if life is already at zero, else reduce it by one
+        state.saturating_sub(1)
+    } else if spawn && state == 0 {
+        // spawn cube if spot is empty and neighbour count
+        // is within specified range
+        alive
+    } else if state != 0 && rules.heal() > 0 {
+        healed(state)
+    } else {
+        state
+    }
+}
+
+// new state of a cell during a second-order step, given its state in the
+// generation before, see 'Scheme::SecondOrder'
+pub(crate) fn second_order_state(
+    state: u8,
+    before: u8,
+    survive: bool,
+    spawn: bool,
+    rules: &Rules,
+) -> u8 {
+    let alive = state_from_automaton(Some(Automaton::new(rules.life())));
+    let state = if state != 0 { alive } else { 0 };
+    let occupied = next_state(state, survive, spawn, rules) == alive;
+    if occupied != (before != 0) {
+        alive
+    } else {
+        0
+    }
+}
+
+// which cells count as neighbours
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Occupancy {
+    // every cube
+    Any,
+    // only cubes in the given state, i.e. with full life
+    Only(u8),
+}
+
+impl Occupancy {
+    pub(crate) fn new(rules: &Rules) -> Self {
+        match rules.semantics() {
+            Semantics::Legacy => Self::Any,
+            Semantics::Standard => {
+                Self::Only(state_from_automaton(Some(Automaton::new(rules.life()))))
+            }
+        }
+    }
+    pub(crate) fn counts(self, state: u8) -> bool {
+        match self {
+            Self::Any => state != 0,
+            Self::Only(s) => state == s,
+        }
+    }
+}
+
+// seed and generation the random numbers of stochastic rules are derived
+// from (see 'Rules::is_stochastic'), every cell draws its own numbers
+// from a hash of both and its position, so the result does not depend on
+// the thread scheduling or on the way neighbours are counted
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Noise {
+    seed: u64,
+    generation: u64,
+}
+
+impl Noise {
+    pub fn new(seed: u64, generation: u64) -> Self {
+        Self { seed, generation }
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    // random number 'draw' of the cell at 'ixyz'
+    pub fn random(&self, ixyz: (isize, isize, isize), draw: u64) -> u64 {
+        [
+            self.generation,
+            ixyz.0 as u64,
+            ixyz.1 as u64,
+            ixyz.2 as u64,
+            draw,
+        ]
+        .iter()
+        .fold(mix(self.seed), |h, &v| {
+            mix(h ^ v.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        })
+    }
+}
+
+// finalizer of SplitMix64
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// outcome of the rules for every possible neighbour count
+// for stochastic rules, 'survive' and 'spawn' tell whether the outcome
+// is possible at all, see 'Lookup::draw'
+#[derive(Debug, Clone)]
+pub(crate) struct Lookup {
+    pub(crate) survive: Arc<[bool]>,
+    pub(crate) spawn: Arc<[bool]>,
+    // chances to survive and spawn, and the chance of decay
+    chances: Option<(Vec<Chance>, Vec<Chance>, Chance)>,
+}
+
+impl Lookup {
+    pub(crate) fn new(rules: &Rules, max_count: usize) -> Self {
+        // tables already hold the outcome of every configuration
+        if let Some(table) = rules.table() {
+            return Self {
+                survive: table.survive().clone(),
+                spawn: table.spawn().clone(),
+                chances: None,
+            };
+        }
+        Self {
+            survive: (0..=max_count).map(|n| !rules.check_despawn(n)).collect(),
+            spawn: (0..=max_count).map(|n| rules.check_spawn(n)).collect(),
+            chances: rules.is_stochastic().then(|| {
+                (
+                    (0..=max_count).map(|n| rules.survive_chance(n)).collect(),
+                    (0..=max_count).map(|n| rules.spawn_chance(n)).collect(),
+                    rules.decay(),
+                )
+            }),
+        }
+    }
+    pub(crate) fn is_stochastic(&self) -> bool {
+        self.chances.is_some()
+    }
+    // whether a cell at 'ixyz' with 'n' neighbours survives and spawns,
+    // 'occupied' tells whether it holds a cube, which may decay
+    // survival, spawning and decay use draws 0, 1 and 2 of the cell
+    pub(crate) fn draw(
+        &self,
+        n: usize,
+        ixyz: (isize, isize, isize),
+        occupied: bool,
+        noise: Noise,
+    ) -> (bool, bool) {
+        let Some((survive, spawn, decay)) = &self.chances else {
+            return (self.survive[n], self.spawn[n]);
+        };
+        let decays = occupied && decay.occurs(noise.random(ixyz, 2));
+        (
+            survive[n].occurs(noise.random(ixyz, 0)) && !decays,
+            spawn[n].occurs(noise.random(ixyz, 1)),
+        )
+    }
+}
+
+// highest amount of life a cell can hold in its state byte
+pub const MAX_LIFE: isize = u8::MAX as isize - 1;
+
+pub(crate) fn state_from_automaton(automaton: Option<Automaton>) -> u8 {
+    match automaton {
+        Some(at) => {
+            debug_assert!((0..=MAX_LIFE).contains(&at.life()));
+            (at.life() + 1) as u8
+        }
+        None => 0,
+    }
+}
+
+pub(crate) fn automaton_from_state(state: u8) -> Option<Automaton> {
+    match state {
+        0 => None,
+        s => Some(Automaton::new(s as isize - 1)),
+    }
+}
+
+// the rules of a competing species, prepared for a step
+struct Member<'a> {
+    rules: &'a Rules,
+    stencil: Stencil,
+    // weights of the plain neighbourhood, which count the parents
+    parents: Vec<usize>,
+    lookup: Lookup,
+    occupancy: Occupancy,
+    alive: u8,
+}
+
+impl<'a> Member<'a> {
+    fn new(rules: &'a Rules) -> Self {
+        let counting = rules.counting();
+        Self {
+            rules,
+            stencil: Stencil::new(&counting),
+            parents: rules.neighbourhood().weights(),
+            lookup: Lookup::new(rules, counting.max_count()),
+            occupancy: Occupancy::new(rules),
+            alive: state_from_automaton(Some(Automaton::new(rules.life()))),
+        }
+    }
+}
+
+// neighbour offsets of a neighbourhood, both as relative coordinates
+// and as relative positions within a chunk
+// the latter are only valid for cells whose neighbours all lie in the
+// same chunk, see 'is_local'
+// 'weights' holds the amount each neighbour adds to the count
+#[derive(Debug, Clone)]
+struct Stencil {
+    offsets: Vec<(isize, isize, isize)>,
+    deltas: Vec<isize>,
+    weights: Vec<usize>,
+    reach: usize,
+}
+
+impl Stencil {
+    fn new(neighbourhood: &Neighbourhood) -> Self {
+        let offsets = neighbourhood.offsets();
+        let c = CHUNK as isize;
+        let deltas = offsets.iter().map(|o| (o.0 * c + o.1) * c + o.2).collect();
+        Self {
+            offsets,
+            deltas,
+            weights: neighbourhood.weights(),
+            reach: neighbourhood.reach(),
+        }
+    }
+    fn is_interior(&self, uxyz: (usize, usize, usize), dims: &SystemDims) -> bool {
+        uxyz.0 >= self.reach
+            && uxyz.1 >= self.reach
+            && uxyz.2 >= self.reach
+            && uxyz.0 + self.reach < dims.x
+            && uxyz.1 + self.reach < dims.y
+            && uxyz.2 + self.reach < dims.z
+    }
+    // all neighbours of an interior cell lie in its own chunk
+    fn is_local(&self, uxyz: (usize, usize, usize)) -> bool {
+        let local = self.reach..CHUNK.saturating_sub(self.reach);
+        local.contains(&(uxyz.0 % CHUNK))
+            && local.contains(&(uxyz.1 % CHUNK))
+            && local.contains(&(uxyz.2 % CHUNK))
+    }
+}
+
+// neighbour counts of every cell, kept up to date by adding births and
+// subtracting deaths instead of counting again during every step
+#[derive(Debug, Clone)]
+struct CountField {
+    neighbourhood: Neighbourhood,
+    occupancy: Occupancy,
+    stencil: Stencil,
+    counts: Chunks<u32>,
+    // positions beyond the walls and the cells they refer to, sorted
+    // by the index of the cell
+    halo: Vec<((isize, isize, isize), usize)>,
+}
+
+impl CountField {
+    fn new(system: &AutoSystem3d, neighbourhood: &Neighbourhood, occupancy: Occupancy) -> Self {
+        let dims = system.dims;
+        let stencil = Stencil::new(neighbourhood);
+        let mut counts = Chunks::new(&dims);
+        counts.columns_mut().for_each(|mut column| {
+            for i in column.range_x() {
+                for j in column.range_y() {
+                    for k in dims.range_z() {
+                        let n = system.count_neighbours((i, j, k), &dims, &stencil, occupancy);
+                        column.set((i, j, k), n as u32);
+                    }
+                }
+            }
+        });
+        let mut halo = system.boundaries.halo(stencil.reach, &dims);
+        halo.sort_by_key(|h| h.1);
+        Self {
+            neighbourhood: neighbourhood.clone(),
+            occupancy,
+            stencil,
+            counts,
+            halo,
+        }
+    }
+    // a cell at 'uxyz' started (+1) or stopped (-1) counting as a
+    // neighbour, e.g. it was spawned or despawned, so every cell
+    // that has it as a neighbour needs to be updated
+    fn add(&mut self, uxyz: (usize, usize, usize), delta: i8, dims: &SystemDims) {
+        // the weight of every neighbour, negated if 'delta' is negative
+        let steps: Vec<u32> = self
+            .stencil
+            .weights
+            .iter()
+            .map(|&w| (w as u32).wrapping_mul(delta as u32))
+            .collect();
+        let ixyz = (uxyz.0 as isize, uxyz.1 as isize, uxyz.2 as isize);
+        let mut add = |q: (isize, isize, isize), step: u32| {
+            let q = (q.0 as usize, q.1 as usize, q.2 as usize);
+            self.counts.set(q, self.counts.get(q).wrapping_add(step));
+        };
+        let interior = self.stencil.is_interior(uxyz, dims);
+        if interior && self.stencil.is_local(uxyz) {
+            let (chunk, idx) = self.counts.chunk_mut(uxyz);
+            for (d, step) in self.stencil.deltas.iter().zip(&steps) {
+                let q = (idx as isize - d) as usize;
+                chunk[q] = chunk[q].wrapping_add(*step);
+            }
+        } else if interior {
+            for (o, step) in self.stencil.offsets.iter().zip(&steps) {
+                add((ixyz.0 - o.0, ixyz.1 - o.1, ixyz.2 - o.2), *step);
+            }
+        } else {
+            // the cell is seen by its neighbours within the system, and
+            // by cells whose neighbourhood reaches beyond a wall and
+            // ends up at this cell
+            let idx = (uxyz.0 * dims.y + uxyz.1) * dims.z + uxyz.2;
+            let start = self.halo.partition_point(|h| h.1 < idx);
+            let end = self.halo.partition_point(|h| h.1 <= idx);
+            let images = self.halo[start..end].iter().map(|h| h.0);
+            for p in std::iter::once(ixyz).chain(images) {
+                for (o, step) in self.stencil.offsets.iter().zip(&steps) {
+                    let q = (p.0 - o.0, p.1 - o.1, p.2 - o.2);
+                    if (0..dims.x as isize).contains(&q.0)
+                        && (0..dims.y as isize).contains(&q.1)
+                        && (0..dims.z as isize).contains(&q.2)
+                    {
+                        add(q, *step);
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl AutoSystem3d {
     pub fn new_from_dims(dims: &SystemDims) -> Self {
         Self {
-            data: vec![vec![vec![Option::<Automaton>::None; dims.z]; dims.y]; dims.x],
+            data: Chunks::new(dims),
+            previous: Chunks::new(dims),
+            species: Chunks::new(dims),
+            dims: *dims,
+            population: 0,
+            stepping: Stepping::default(),
+            scheme: Scheme::default(),
+            blocks: Blocks::new(dims),
+            last_step: None,
+            count_field: None,
+            boundaries: Boundaries::default(),
+        }
+    }
+    // approximate number of bytes a system of the given size occupies once
+    // every chunk is allocated, including the buffers needed while stepping
+    // with the given rules, stepping and update scheme
+    pub fn estimate_memory(
+        dims: &SystemDims,
+        rules: &Rules,
+        stepping: Stepping,
+        scheme: Scheme,
+    ) -> usize {
+        let chunks = dims.x.div_ceil(CHUNK) * dims.y.div_ceil(CHUNK) * dims.z.div_ceil(CHUNK);
+        // chunks at the far walls are allocated in full
+        let cells = chunks * CHUNK.pow(3);
+        let table = chunks * std::mem::size_of::<Option<Box<[u8]>>>();
+        // current and previous generation, the species only take their
+        // table unless several species compete
+        let mut bytes = 2 * cells + 3 * table;
+        // changed and active blocks, and the flags recorded during a step
+        bytes += 3 * dims.max_amount().div_ceil(BLOCK.pow(3));
+        // competing species and block rules neither use the stepping nor
+        // the update scheme, see 'step'
+        if rules.competition().is_some() {
+            // the species of every cube, and their owners in the new state
+            return bytes + 2 * cells + table;
+        }
+        if rules.blocks().is_some() {
+            return bytes;
+        }
+        match (stepping, scheme) {
+            // sequential steps count the neighbours of one cell at a time
+            (Stepping::Cellwise, _) | (Stepping::Bitwise, Scheme::Sequential) => {}
+            (Stepping::Bitwise, _) => {
+                bytes += (dims.x + 2) * (dims.y + 2) * (dims.z + 2).div_ceil(64) * 8
+            }
+            // one neighbour count per cell
+            (Stepping::Incremental, _) => bytes += 4 * cells + table,
+        }
+        match scheme {
+            // every cell and its random rank
+            Scheme::Sequential => {
+                bytes += dims.max_amount() * std::mem::size_of::<(u64, (isize, isize, isize))>()
+            }
+            // the state before the first half
+            Scheme::Checkerboard => bytes += cells + table,
+            _ => {}
         }
+        bytes
+    }
+    pub fn dims(&self) -> SystemDims {
+        self.dims
+    }
+    pub fn stepping(&self) -> Stepping {
+        self.stepping
+    }
+    pub fn set_stepping(&mut self, stepping: Stepping) {
+        self.stepping = stepping;
+    }
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.scheme = scheme;
+    }
+    pub fn boundaries(&self) -> Boundaries {
+        self.boundaries
+    }
+    // every cell close to a wall may change, so the next step evaluates
+    // the entire system
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+        self.last_step = None;
+        self.count_field = None;
+    }
+    // remove all cubes, the stepping strategy, the update scheme and the
+    // boundaries are kept
+    pub fn clear(&mut self) {
+        let (stepping, scheme, boundaries) = (self.stepping, self.scheme, self.boundaries);
+        *self = Self::new_from_dims(&self.dims);
+        self.stepping = stepping;
+        self.scheme = scheme;
+        self.boundaries = boundaries;
     }
     pub fn access_xyz(&mut self, xyz: (usize, usize, usize), value: Option<Automaton>) {
-        self.data[xyz.0][xyz.1][xyz.2] = value;
+        self.species.set(xyz, value.map_or(0, |at| at.species()));
+        let new = state_from_automaton(value);
+        let old = self.data.set(xyz, new);
+        self.track_population(old, new);
+        self.blocks.mark(xyz);
+        if let Some(field) = &mut self.count_field {
+            match (field.occupancy.counts(old), field.occupancy.counts(new)) {
+                (false, true) => field.add(xyz, 1, &self.dims),
+                (true, false) => field.add(xyz, -1, &self.dims),
+                _ => {}
+            }
+        }
     }
     pub fn set_xyz(&mut self, xyz: (usize, usize, usize), value: Automaton) {
-        self.data[xyz.0][xyz.1][xyz.2] = Some(value);
+        self.access_xyz(xyz, Some(value));
     }
     pub fn delete_xyz(&mut self, xyz: (usize, usize, usize)) {
-        self.data[xyz.0][xyz.1][xyz.2] = Option::<Automaton>::None;
+        self.access_xyz(xyz, None);
+    }
+    // number of occupied cells
+    pub fn population(&self) -> usize {
+        self.population
+    }
+    fn track_population(&mut self, old: u8, new: u8) {
+        match (old, new) {
+            (0, 0) => {}
+            (0, _) => self.population += 1,
+            (_, 0) => self.population -= 1,
+            _ => {}
+        }
     }
     pub fn rem_euclid_bool(&self, xyz: (isize, isize, isize), dims: &SystemDims) -> bool {
-        let (x, y, z) = rem_euclid_3d(xyz, dims);
-        self.data[x][y][z].is_some()
+        self.data.get(rem_euclid_3d(xyz, dims)) != 0
     }
     pub fn count_neighbours_moore(&self, uxyz: (usize, usize, usize), dims: &SystemDims) -> usize {
-        let mut count = 0usize;
-        for ixyz in neighbours_moore_3d(uxyz) {
-            if self.rem_euclid_bool(ixyz, dims) {
-                count += 1;
-            }
-        }
-        count
+        self.count_neighbours(
+            uxyz,
+            dims,
+            &Stencil::new(&Neighbourhood::Moore(1)),
+            Occupancy::Any,
+        )
     }
     pub fn count_neighbours_von_neumann(
         &self,
         uxyz: (usize, usize, usize),
         dims: &SystemDims,
     ) -> usize {
-        let mut count = 0usize;
-        for ixyz in neighbours_von_neumann_3d(uxyz) {
-            if self.rem_euclid_bool(ixyz, dims) {
-                count += 1;
-            }
+        self.count_neighbours(
+            uxyz,
+            dims,
+            &Stencil::new(&Neighbourhood::VonNeumann(1)),
+            Occupancy::Any,
+        )
+    }
+    // cells whose neighbours lie in the same chunk are counted by plain
+    // index arithmetic, only cells close to the walls need to wrap around
+    fn count_neighbours(
+        &self,
+        uxyz: (usize, usize, usize),
+        dims: &SystemDims,
+        stencil: &Stencil,
+        occupancy: Occupancy,
+    ) -> usize {
+        let interior = stencil.is_interior(uxyz, dims);
+        if interior && stencil.is_local(uxyz) {
+            // an empty chunk has no neighbours to count
+            let Some((chunk, idx)) = self.data.chunk_of(uxyz) else {
+                return 0;
+            };
+            stencil
+                .deltas
+                .iter()
+                .zip(&stencil.weights)
+                .filter(|(&d, _)| occupancy.counts(chunk[(idx as isize + d) as usize]))
+                .map(|(_, w)| w)
+                .sum()
+        } else if interior {
+            stencil
+                .offsets
+                .iter()
+                .zip(&stencil.weights)
+                .filter(|(o, _)| {
+                    let q = (
+                        (uxyz.0 as isize + o.0) as usize,
+                        (uxyz.1 as isize + o.1) as usize,
+                        (uxyz.2 as isize + o.2) as usize,
+                    );
+                    occupancy.counts(self.data.get(q))
+                })
+                .map(|(_, w)| w)
+                .sum()
+        } else {
+            let ixyz = (uxyz.0 as isize, uxyz.1 as isize, uxyz.2 as isize);
+            stencil
+                .offsets
+                .iter()
+                .zip(&stencil.weights)
+                .filter(|(o, _)| {
+                    self.is_occupied((ixyz.0 + o.0, ixyz.1 + o.1, ixyz.2 + o.2), dims, occupancy)
+                })
+                .map(|(_, w)| w)
+                .sum()
+        }
+    }
+    // occupancy of a neighbour, which may lie beyond the walls
+    fn is_occupied(
+        &self,
+        ixyz: (isize, isize, isize),
+        dims: &SystemDims,
+        occupancy: Occupancy,
+    ) -> bool {
+        match self.boundaries.resolve(ixyz, dims) {
+            Neighbour::Cell(uxyz) => occupancy.counts(self.data.get(uxyz)),
+            Neighbour::Empty => false,
+            Neighbour::Occupied => true,
         }
-        count
     }
     pub fn get_at_xyz(&self, uxyz: (usize, usize, usize)) -> Option<Automaton> {
-        self.data[uxyz.0][uxyz.1][uxyz.2]
+        automaton_from_state(self.data.get(uxyz)).map(|at| at.with_species(self.species.get(uxyz)))
     }
-    pub fn apply_changes(&mut self, changes: &Vec<SysChange>) {
+    // number of cubes of every species, see 'Competition'
+    pub fn census(&self, species: usize) -> Vec<usize> {
+        let mut census = vec![0; species];
+        if let Some(first) = census.first_mut() {
+            *first = self.population;
+        }
+        // cubes of the first species are counted by subtracting all others,
+        // which are found among the cells whose species is not 0
+        let first = Chunks::new(&self.dims);
+        for uxyz in self.species.differences(&first) {
+            if self.data.get(uxyz) != 0 {
+                census[0] -= 1;
+                census[self.species.get(uxyz) as usize] += 1;
+            }
+        }
+        census
+    }
+    pub fn apply_changes(&mut self, changes: &[SysChange]) {
         for ele in changes {
-            self.data[ele.x()][ele.y()][ele.z()] = ele.element();
+            self.access_xyz(ele.uxyz, ele.element());
+        }
+    }
+    // advance the system by one generation
+    // this only operates on plain data, visuals (if any) have to be
+    // synchronised afterwards using the returned report
+    // new cubes are only spawned if 'growth' is set
+    // stochastic rules and update schemes draw their random numbers
+    // from 'noise'
+    // block rules take the partition from the generation of 'noise' and
+    // do not depend on the update scheme, neither do competing species
+    pub fn step(
+        &mut self,
+        rules: &Rules,
+        dims: &SystemDims,
+        growth: bool,
+        noise: Noise,
+    ) -> StepReport {
+        debug_assert_eq!(*dims, self.dims);
+        if let Some(competition) = rules.competition() {
+            return self.step_species(competition, growth, noise);
+        }
+        if let Some(rule) = rules.blocks() {
+            let shifted = noise.generation() % 2 == 1;
+            return self.step_blocks(rules, rule, growth, shifted);
+        }
+        match self.scheme {
+            Scheme::Synchronous | Scheme::Asynchronous(_) => {
+                self.step_half(rules, dims, (growth, noise), 0)
+            }
+            // withholding spawns could not be undone
+            Scheme::SecondOrder => self.step_half(rules, dims, (true, noise), 0),
+            Scheme::Sequential => self.step_sequential(rules, dims, growth, noise),
+            // the previous generation and the changed blocks have to
+            // cover both halves
+            Scheme::Checkerboard => {
+                let before = self.data.clone();
+                let first = self.step_half(rules, dims, (growth, noise), 0);
+                let blocks = self.blocks.clone();
+                let second = self.step_half(rules, dims, (growth, noise), 1);
+                self.blocks.include(&blocks);
+                self.previous = before;
+                first.merge(second).with_population(self.population)
+            }
         }
     }
+    // update all cells that are due during the given half of a step, see
+    // 'Scheme::updates', at once
+    fn step_half(
+        &mut self,
+        rules: &Rules,
+        dims: &SystemDims,
+        (growth, noise): (bool, Noise),
+        half: usize,
+    ) -> StepReport {
+        // the count field is only maintained while it is in use
+        let occupancy = Occupancy::new(rules);
+        let counting = rules.counting();
+        match (self.stepping, &self.count_field) {
+            (Stepping::Incremental, Some(field))
+                if field.neighbourhood == counting && field.occupancy == occupancy => {}
+            (Stepping::Incremental, _) => {
+                self.count_field = Some(CountField::new(self, &counting, occupancy))
+            }
+            _ => self.count_field = None,
+        }
+        let stencil = Stencil::new(&counting);
+        let max_count = counting.max_count();
+        let counting = match self.stepping {
+            Stepping::Cellwise => Counting::Cellwise,
+            Stepping::Bitwise => Counting::Bitwise(
+                BitGrid::from_chunks(&self.data, dims, &self.boundaries, occupancy, &counting),
+                count_set(|n| !rules.check_despawn(n), max_count, rules),
+                count_set(|n| rules.check_spawn(n), max_count, rules),
+            ),
+            Stepping::Incremental => Counting::Incremental,
+        };
+        // only evaluate blocks in the vicinity of recent changes
+        // if cubes spawn without any neighbours, or if the settings
+        // changed since the last step, empty space may change as well,
+        // and by chance, or if cells are not updated in every step, any
+        // cell may change
+        let spawns_on_zero = rules.spawns_on_zero() && growth;
+        let settings_changed = match &self.last_step {
+            Some((r, g)) => r != rules || *g != growth,
+            None => true,
+        };
+        // twisted walls connect blocks that are not next to each other
+        let active = if spawns_on_zero
+            || settings_changed
+            || self.boundaries.is_twisted()
+            || rules.is_stochastic()
+            || self.scheme != Scheme::Synchronous
+        {
+            None
+        } else {
+            Some(self.blocks.active(stencil.reach))
+        };
+        let lookup = Lookup::new(rules, max_count);
+        let recorder = self.blocks.recorder();
+        // the back buffer still holds the previous generation, it is
+        // entirely overwritten by the new state, except for blocks that
+        // are not evaluated: these did not change during the last step,
+        // so both buffers are identical there
+        let mut next = std::mem::take(&mut self.previous);
+        // concurrently iterate over the system, every task writes its
+        // own column of chunks of the new state
+        let report = next
+            .columns_mut()
+            .map(|cells| {
+                let mut slab = Slab {
+                    rules,
+                    lookup: &lookup,
+                    growth,
+                    noise,
+                    scheme: self.scheme,
+                    half,
+                    cells,
+                    active: active.as_deref(),
+                    recorder: &recorder,
+                    report: StepReport::default(),
+                };
+                match &counting {
+                    Counting::Cellwise => self.step_slab_cellwise(&mut slab, |uxyz| {
+                        self.count_neighbours(uxyz, dims, &stencil, occupancy)
+                    }),
+                    Counting::Incremental => {
+                        let field = self.count_field.as_ref().unwrap();
+                        self.step_slab_cellwise(&mut slab, |uxyz| field.counts.get(uxyz) as usize)
+                    }
+                    Counting::Bitwise(grid, survive, spawn) => {
+                        let sets = (survive.as_deref(), spawn.as_deref());
+                        self.step_slab_bitwise(grid, sets, &mut slab)
+                    }
+                }
+                slab.cells.trim();
+                slab.report
+            })
+            .reduce(StepReport::default, StepReport::merge);
+        // swap buffers, the current state becomes the previous one
+        self.previous = std::mem::replace(&mut self.data, next);
+        self.blocks.record(recorder);
+        self.update_count_field(occupancy, dims);
+        if settings_changed {
+            self.last_step = Some((rules.clone(), growth));
+        }
+        self.population = self.population + report.births - report.deaths;
+        report.with_population(self.population)
+    }
+    // update the cells one by one in a random order, changing the state
+    // in place, so every cell sees the cells updated before it
+    fn step_sequential(
+        &mut self,
+        rules: &Rules,
+        dims: &SystemDims,
+        growth: bool,
+        noise: Noise,
+    ) -> StepReport {
+        let occupancy = Occupancy::new(rules);
+        let counting = rules.counting();
+        match (self.stepping, &self.count_field) {
+            (Stepping::Incremental, Some(field))
+                if field.neighbourhood == counting && field.occupancy == occupancy => {}
+            (Stepping::Incremental, _) => {
+                self.count_field = Some(CountField::new(self, &counting, occupancy))
+            }
+            _ => self.count_field = None,
+        }
+        let stencil = Stencil::new(&counting);
+        let lookup = Lookup::new(rules, counting.max_count());
+        let mut order: Vec<(u64, (isize, isize, isize))> = (0..dims.max_amount())
+            .into_par_iter()
+            .map(|idx| {
+                let uxyz = (idx / (dims.y * dims.z), idx / dims.z % dims.y, idx % dims.z);
+                Scheme::order(isizify3(uxyz.0, uxyz.1, uxyz.2), noise)
+            })
+            .collect();
+        order.par_sort_unstable();
+        self.previous.clone_from(&self.data);
+        let recorder = self.blocks.recorder();
+        let mut report = StepReport::default();
+        for (_, ixyz) in order {
+            let uxyz = (ixyz.0 as usize, ixyz.1 as usize, ixyz.2 as usize);
+            let n = self.count_neighbours(uxyz, dims, &stencil, occupancy);
+            let state = self.data.get(uxyz);
+            let (survive, spawn) = lookup.draw(n, ixyz, state != 0, noise);
+            let new = next_state(state, survive, spawn && growth, rules);
+            if new != state {
+                self.data.set(uxyz, new);
+                report.count(state, new);
+                self.blocks.record_change(&recorder, uxyz);
+            }
+        }
+        self.data.trim();
+        self.blocks.record(recorder);
+        self.update_count_field(occupancy, dims);
+        self.last_step = Some((rules.clone(), growth));
+        self.population = self.population + report.births - report.deaths;
+        report.with_population(self.population)
+    }
+    // replace every block of 2x2x2 cells by its new configuration, see
+    // 'BlockRule', the blocks start at even coordinates, or at odd ones
+    // if 'shifted'
+    // blocks that stick out of the system are left as they are, unless
+    // the axis wraps around and its length is even
+    // unless 'growth' is set, blocks that would gain cubes are left as
+    // they are as well
+    fn step_blocks(
+        &mut self,
+        rules: &Rules,
+        rule: &BlockRule,
+        growth: bool,
+        shifted: bool,
+    ) -> StepReport {
+        let dims = self.dims;
+        let alive = state_from_automaton(Some(Automaton::new(rules.life())));
+        let lengths = [dims.x, dims.y, dims.z];
+        let boundaries = [self.boundaries.x, self.boundaries.y, self.boundaries.z];
+        // position of a cell within its block along an axis, and the
+        // position of the other cell of the block along this axis
+        let along = |a: usize, c: usize| -> Option<(usize, usize)> {
+            let len = lengths[a];
+            let wraps = boundaries[a] == Boundary::Wrap && len.is_multiple_of(2);
+            let p = (c + shifted as usize) % 2;
+            let other = match p {
+                0 if c + 1 < len => c + 1,
+                0 if wraps => 0,
+                1 if c > 0 => c - 1,
+                1 if wraps => len - 1,
+                _ => return None,
+            };
+            Some((p, other))
+        };
+        let recorder = self.blocks.recorder();
+        let mut next = std::mem::take(&mut self.previous);
+        let report = next
+            .columns_mut()
+            .map(|mut cells| {
+                let mut report = StepReport::default();
+                for i in cells.range_x() {
+                    for j in cells.range_y() {
+                        for k in dims.range_z() {
+                            let uxyz = (i, j, k);
+                            let state = self.data.get(uxyz);
+                            let (Some(x), Some(y), Some(z)) =
+                                (along(0, i), along(1, j), along(2, k))
+                            else {
+                                cells.set(uxyz, state);
+                                continue;
+                            };
+                            let pick = |own: (usize, usize), c: usize, b: usize| {
+                                if b == own.0 {
+                                    c
+                                } else {
+                                    own.1
+                                }
+                            };
+                            let mut configuration = 0u8;
+                            for b in 0..8 {
+                                let (bx, by, bz) = (b >> 2 & 1, b >> 1 & 1, b & 1);
+                                let cell = (pick(x, i, bx), pick(y, j, by), pick(z, k, bz));
+                                if self.data.get(cell) != 0 {
+                                    configuration |= 1 << block_bit(bx, by, bz);
+                                }
+                            }
+                            let mut new = rule.apply(configuration);
+                            if !growth && new.count_ones() > configuration.count_ones() {
+                                new = configuration;
+                            }
+                            let occupied = new >> block_bit(x.0, y.0, z.0) & 1 == 1;
+                            let new = match (state, occupied) {
+                                (0, true) => alive,
+                                (_, false) => 0,
+                                (s, true) => s,
+                            };
+                            cells.set(uxyz, new);
+                            if new != state {
+                                report.count(state, new);
+                                self.blocks.record_change(&recorder, uxyz);
+                            }
+                        }
+                    }
+                }
+                cells.trim();
+                report
+            })
+            .reduce(StepReport::default, StepReport::merge);
+        self.previous = std::mem::replace(&mut self.data, next);
+        self.blocks.record(recorder);
+        // the count field and the active blocks do not apply to block rules
+        self.count_field = None;
+        self.last_step = None;
+        self.population = self.population + report.births - report.deaths;
+        report.with_population(self.population)
+    }
+    // apply the rules of every species cell by cell, see 'Competition'
+    // occupied cells follow the rules of their own species, empty cells
+    // are claimed by one of the species whose spawn condition applies
+    // stochastic rules of all species share the random numbers of a cell,
+    // the owner of a new cube is picked with draw 5
+    fn step_species(
+        &mut self,
+        competition: &Competition,
+        growth: bool,
+        noise: Noise,
+    ) -> StepReport {
+        let dims = self.dims;
+        let members: Vec<Member> = competition.species().iter().map(Member::new).collect();
+        let neighbours = competition.neighbours();
+        let recorder = self.blocks.recorder();
+        let mut next = std::mem::take(&mut self.previous);
+        let mut owners = Chunks::new(&dims);
+        let report = next
+            .columns_mut()
+            .zip(owners.columns_mut())
+            .map(|(mut cells, mut owners)| {
+                let mut report = StepReport::default();
+                let mut candidates = Vec::with_capacity(members.len());
+                for i in cells.range_x() {
+                    for j in cells.range_y() {
+                        for k in dims.range_z() {
+                            let uxyz = (i, j, k);
+                            let ixyz = isizify3(i, j, k);
+                            let state = self.data.get(uxyz);
+                            let (new, owner) = if state != 0 {
+                                let owner = self.species.get(uxyz);
+                                let member = &members[owner as usize];
+                                let (n, _) = self.count_species(uxyz, owner, &members, neighbours);
+                                let (survive, _) = member.lookup.draw(n, ixyz, true, noise);
+                                (next_state(state, survive, false, member.rules), owner)
+                            } else {
+                                candidates.clear();
+                                for (s, member) in members.iter().enumerate() {
+                                    let (n, parents) =
+                                        self.count_species(uxyz, s as u8, &members, neighbours);
+                                    if parents == 0 {
+                                        continue;
+                                    }
+                                    let (survive, spawn) =
+                                        member.lookup.draw(n, ixyz, false, noise);
+                                    if next_state(0, survive, spawn && growth, member.rules) != 0 {
+                                        candidates.push((s as u8, parents));
+                                    }
+                                }
+                                match candidates.len() {
+                                    0 => (0, 0),
+                                    _ => {
+                                        let owner = competition
+                                            .ownership()
+                                            .pick(&candidates, noise.random(ixyz, 5));
+                                        (members[owner as usize].alive, owner)
+                                    }
+                                }
+                            };
+                            cells.set(uxyz, new);
+                            owners.set(uxyz, if new != 0 { owner } else { 0 });
+                            if new != state {
+                                report.count(state, new);
+                                self.blocks.record_change(&recorder, uxyz);
+                            }
+                        }
+                    }
+                }
+                cells.trim();
+                report
+            })
+            .reduce(StepReport::default, StepReport::merge);
+        self.previous = std::mem::replace(&mut self.data, next);
+        self.species = owners;
+        self.blocks.record(recorder);
+        self.count_field = None;
+        self.last_step = None;
+        self.population = self.population + report.births - report.deaths;
+        report.with_population(self.population)
+    }
+    // neighbour count of a cell according to the rules of the given
+    // species, and the number of its parents, i.e. the cubes of this
+    // species within its neighbourhood, regardless of the weights that
+    // pack split counts, see 'Rules::counting'
+    // every cube counts as a neighbour according to the semantics of its
+    // own species, cubes beyond an occupied wall belong to no species
+    fn count_species(
+        &self,
+        uxyz: (usize, usize, usize),
+        species: u8,
+        members: &[Member],
+        neighbours: Neighbours,
+    ) -> (usize, usize) {
+        let member = &members[species as usize];
+        let stencil = &member.stencil;
+        let counts = |uxyz: (usize, usize, usize)| {
+            let owner = self.species.get(uxyz);
+            (
+                members[owner as usize]
+                    .occupancy
+                    .counts(self.data.get(uxyz)),
+                owner == species,
+            )
+        };
+        let interior = stencil.is_interior(uxyz, &self.dims);
+        let ixyz = isizify3(uxyz.0, uxyz.1, uxyz.2);
+        let (mut n, mut parents) = (0, 0);
+        for (o, (weight, plain)) in stencil
+            .offsets
+            .iter()
+            .zip(stencil.weights.iter().zip(&member.parents))
+        {
+            let q = (ixyz.0 + o.0, ixyz.1 + o.1, ixyz.2 + o.2);
+            let (occupied, own) = if interior {
+                counts((q.0 as usize, q.1 as usize, q.2 as usize))
+            } else {
+                match self.boundaries.resolve(q, &self.dims) {
+                    Neighbour::Cell(u) => counts(u),
+                    Neighbour::Occupied => (true, false),
+                    Neighbour::Empty => (false, false),
+                }
+            };
+            if occupied && (own || neighbours == Neighbours::All) {
+                n += weight;
+            }
+            if occupied && own {
+                parents += plain;
+            }
+        }
+        (n, parents)
+    }
+    // swap the current and the previous generation, so that second-order
+    // steps run backwards in time, see 'Scheme::SecondOrder'
+    // the changes show up in 'last_changes' just like those of a step
+    pub fn reverse(&mut self) -> StepReport {
+        std::mem::swap(&mut self.data, &mut self.previous);
+        let recorder = self.blocks.recorder();
+        let mut report = StepReport::default();
+        for uxyz in self.data.differences(&self.previous) {
+            report.count(self.previous.get(uxyz), self.data.get(uxyz));
+            self.blocks.record_change(&recorder, uxyz);
+        }
+        self.blocks.record(recorder);
+        self.count_field = None;
+        self.last_step = None;
+        self.population = self.population + report.births - report.deaths;
+        report.with_population(self.population)
+    }
+    // add the changes of the last step to the count field, if in use
+    fn update_count_field(&mut self, occupancy: Occupancy, dims: &SystemDims) {
+        let Some(mut field) = self.count_field.take() else {
+            return;
+        };
+        for change in self.last_changes() {
+            let uxyz = (change.x(), change.y(), change.z());
+            match (
+                occupancy.counts(self.previous.get(uxyz)),
+                occupancy.counts(self.data.get(uxyz)),
+            ) {
+                (false, true) => field.add(uxyz, 1, dims),
+                (true, false) => field.add(uxyz, -1, dims),
+                _ => {}
+            }
+        }
+        self.count_field = Some(field);
+    }
+    fn is_active(&self, active: Option<&[bool]>, b: (usize, usize, usize)) -> bool {
+        active.is_none_or(|active| active[self.blocks.index(b)])
+    }
+    // apply the rules to every single cell in the column of the slab
+    // 'count' returns the number of neighbours of a cell
+    fn step_slab_cellwise(&self, slab: &mut Slab, count: impl Fn((usize, usize, usize)) -> usize) {
+        let dims = &self.dims;
+        let (_, _, nbz) = self.blocks.counts();
+        let active = slab.active;
+        for i in slab.cells.range_x() {
+            for j in slab.cells.range_y() {
+                for bz in (0..nbz).filter(|&bz| self.is_active(active, (i / BLOCK, j / BLOCK, bz)))
+                {
+                    for k in Blocks::cells(bz, dims.z) {
+                        let uxyz = (i, j, k); // the 'u' stands for 'unsigned'
+                                              // count neighbours and apply rules
+                        let n = count(uxyz);
+                        let occupied = self.data.get(uxyz) != 0;
+                        let (survive, spawn) = slab.lookup.draw(
+                            n,
+                            isizify3(uxyz.0, uxyz.1, uxyz.2),
+                            occupied,
+                            slab.noise,
+                        );
+                        self.apply_rules(uxyz, survive, spawn, slab);
+                    }
+                }
+            }
+        }
+    }
+    // count the neighbours of 64 cells at once, then apply the rules
+    // only to cells that are about to change
+    fn step_slab_bitwise(
+        &self,
+        grid: &BitGrid,
+        sets: (Option<&[usize]>, Option<&[usize]>),
+        slab: &mut Slab,
+    ) {
+        let dims = &self.dims;
+        let (_, _, nbz) = self.blocks.counts();
+        let active = slab.active;
+        let mut counts = Vec::new();
+        let mut mask = vec![0u64; grid.words()];
+        let standard = slab.rules.semantics() == Semantics::Standard;
+        let heal = slab.rules.heal() > 0;
+        let weakened_matter = standard || heal;
+        let alive = state_from_automaton(Some(Automaton::new(slab.rules.life())));
+        let mut weakened = vec![0u64; grid.words()];
+        let mut row = vec![0u8; dims.z];
+        let second_order = slab.scheme == Scheme::SecondOrder;
+        for i in slab.cells.range_x() {
+            for j in slab.cells.range_y() {
+                // cells of this row that belong to active blocks
+                mask.fill(0);
+                for bz in (0..nbz).filter(|&bz| self.is_active(active, (i / BLOCK, j / BLOCK, bz)))
+                {
+                    let cells = Blocks::cells(bz, dims.z);
+                    // cells that are not visited below stay as they are, in
+                    // second-order steps every cell is visited, and reads the
+                    // generation before from the slab
+                    if !second_order {
+                        slab.cells.copy_row(&self.data, (i, j), cells.clone());
+                    }
+                    for k in cells {
+                        let (w, b) = grid.cell_bit(k);
+                        mask[w] |= 1 << b;
+                    }
+                }
+                if mask.iter().all(|&m| m == 0) {
+                    continue;
+                }
+                grid.count_row(i, j, &mut counts);
+                // cubes that lost life may change regardless of their
+                // neighbours count: they keep decaying (standard semantics)
+                // or heal (if enabled)
+                if weakened_matter {
+                    weakened.fill(0);
+                    self.data.read_row((i, j), &mut row);
+                    for (k, &s) in row.iter().enumerate() {
+                        if s != 0 && s != alive {
+                            let (w, b) = grid.cell_bit(k);
+                            weakened[w] |= 1 << b;
+                        }
+                    }
+                }
+                for (w, acc) in counts.iter().enumerate() {
+                    let occupied = grid.occupancy(i, j, w);
+                    let survive = grid.select(acc, sets.0, &slab.lookup.survive);
+                    let spawn = grid.select(acc, sets.1, &slab.lookup.spawn);
+                    let mut candidates = occupied & !survive;
+                    // in standard semantics weakened cubes are not part of the
+                    // grid, they decay or heal, in legacy semantics they are
+                    // part of it and only heal within the survival range
+                    candidates |= match standard {
+                        true => weakened[w],
+                        false if heal => weakened[w] & survive,
+                        false => 0,
+                    };
+                    // by chance, any cube may change
+                    if slab.lookup.is_stochastic() {
+                        candidates |= occupied | weakened[w];
+                    }
+                    if second_order {
+                        candidates = !0;
+                    }
+                    if slab.growth {
+                        let empty = !occupied & !weakened[w] & grid.valid_mask(w);
+                        candidates |= match standard {
+                            true => empty & spawn,
+                            false => empty & survive & spawn,
+                        };
+                    }
+                    candidates &= mask[w];
+                    while candidates != 0 {
+                        let b = candidates.trailing_zeros();
+                        candidates &= candidates - 1;
+                        let uxyz = (i, j, grid.bit_cell(w, b));
+                        let (survive, spawn) = match slab.lookup.is_stochastic() {
+                            true => {
+                                let occupied = self.data.get(uxyz) != 0;
+                                let n = grid.count(acc, b);
+                                slab.lookup.draw(
+                                    n,
+                                    isizify3(uxyz.0, uxyz.1, uxyz.2),
+                                    occupied,
+                                    slab.noise,
+                                )
+                            }
+                            false => (survive >> b & 1 == 1, spawn >> b & 1 == 1),
+                        };
+                        self.apply_rules(uxyz, survive, spawn, slab);
+                    }
+                }
+            }
+        }
+    }
+    // write the new state of a single cell to the slab
+    // survive: the neighbour count is within the survival range
+    // spawn: the neighbour count is within the spawn range
+    fn apply_rules(
+        &self,
+        uxyz: (usize, usize, usize),
+        survive: bool,
+        spawn: bool,
+        slab: &mut Slab,
+    ) {
+        let state = self.data.get(uxyz);
+        let ixyz = isizify3(uxyz.0, uxyz.1, uxyz.2);
+        let new = match slab.scheme {
+            // the slab still holds the generation before
+            Scheme::SecondOrder => {
+                second_order_state(state, slab.cells.get(uxyz), survive, spawn, slab.rules)
+            }
+            _ if slab.scheme.updates(ixyz, slab.noise, slab.half) => {
+                next_state(state, survive, spawn && slab.growth, slab.rules)
+            }
+            _ => state,
+        };
+        slab.cells.set(uxyz, new);
+        if new != state {
+            slab.report.count(state, new);
+            self.blocks.record_change(slab.recorder, uxyz);
+        }
+    }
+    // all cells that have been changed by the last step, found by
+    // comparing the current state with the previous generation
+    // changes applied after the step (e.g. by 'set_xyz') show up as well
+    pub fn last_changes(&self) -> impl Iterator<Item = SysChange> + '_ {
+        let dims = self.dims;
+        self.blocks.changed().flat_map(move |b| {
+            Blocks::cells(b.0, dims.x).flat_map(move |i| {
+                Blocks::cells(b.1, dims.y).flat_map(move |j| {
+                    Blocks::cells(b.2, dims.z).filter_map(move |k| {
+                        let uxyz = (i, j, k);
+                        (self.data.get(uxyz) != self.previous.get(uxyz)).then(|| SysChange {
+                            uxyz,
+                            element: self.get_at_xyz(uxyz),
+                        })
+                    })
+                })
+            })
+        })
+    }
+    // populate the entire system pseudorandomly, every x slice gets its
+    // own generator derived from the seed, so the result does not depend
+    // on the thread scheduling
+    // returns the positions of the new cubes
+    pub fn fill_pseudorandom(
+        &mut self,
+        dims: &SystemDims,
+        density: f64,
+        life: isize,
+        seed: u64,
+    ) -> Vec<(usize, usize, usize)> {
+        // every slice draws from its own generator, the slices are
+        // collected in order
+        let spawned: Vec<(usize, usize, usize)> = dims
+            .range_x()
+            .into_par_iter()
+            .flat_map_iter(|i: usize| {
+                let mut rng =
+                    XorA::seed_from_u64(seed.wrapping_add(i.wrapping_mul(999999999) as u64));
+                let mut local_spawned = Vec::<(usize, usize, usize)>::new();
+                for j in dims.range_y() {
+                    for k in dims.range_z() {
+                        if rng.gen_bool(density) && self.get_at_xyz((i, j, k)).is_none() {
+                            local_spawned.push((i, j, k));
+                        }
+                    }
+                }
+                local_spawned
+            })
+            .collect();
+        for uxyz in &spawned {
+            self.set_xyz(*uxyz, Automaton::new(life));
+        }
+        spawned
+    }
+    // populate the center of the system pseudorandomly
+    // the size of the center is defined by 'fract', see 'core_edge'
+    pub fn fill_pseudorandom_core<R: Rng>(
+        &mut self,
+        dims: &SystemDims,
+        fract: usize,
+        density: f64,
+        life: isize,
+        rng: &mut R,
+    ) -> Vec<(usize, usize, usize)> {
+        let mut spawned = Vec::<(usize, usize, usize)>::new();
+        for i in dims.core_range_x(fract) {
+            for j in dims.core_range_y(fract) {
+                for k in dims.core_range_z(fract) {
+                    if rng.gen_bool(density) && self.get_at_xyz((i, j, k)).is_none() {
+                        self.set_xyz((i, j, k), Automaton::new(life));
+                        spawned.push((i, j, k));
+                    }
+                }
+            }
+        }
+        spawned
+    }
     pub fn debug(&self) {
         eprintln!("{:?}", &self);
     }
 }
 
-// I've decided to just write all possible neighbours out
-// nested loops would haven been probably much smarter, but
-// honestly I'm not sure how far compiler optimisations go
-fn neighbours_moore_3d(uxyz: (usize, usize, usize)) -> [(isize, isize, isize); 26] {
-    let xyz = (uxyz.0 as isize, uxyz.1 as isize, uxyz.2 as isize);
-    [
-        (xyz.0 - 1, xyz.1 - 1, xyz.2 - 1),
-        (xyz.0 - 1, xyz.1 - 1, xyz.2),
-        (xyz.0 - 1, xyz.1 - 1, xyz.2 + 1),
-        (xyz.0 - 1, xyz.1, xyz.2 - 1),
-        (xyz.0 - 1, xyz.1, xyz.2),
-        (xyz.0 - 1, xyz.1, xyz.2 + 1),
-        (xyz.0 - 1, xyz.1 + 1, xyz.2 - 1),
-        (xyz.0 - 1, xyz.1 + 1, xyz.2),
-        (xyz.0 - 1, xyz.1 + 1, xyz.2 + 1),
-        (xyz.0, xyz.1 - 1, xyz.2 - 1),
-        (xyz.0, xyz.1 - 1, xyz.2),
-        (xyz.0, xyz.1 - 1, xyz.2 + 1),
-        (xyz.0, xyz.1, xyz.2 - 1),
-        (xyz.0, xyz.1, xyz.2 + 1),
-        (xyz.0, xyz.1 + 1, xyz.2 - 1),
-        (xyz.0, xyz.1 + 1, xyz.2),
-        (xyz.0, xyz.1 + 1, xyz.2 + 1),
-        (xyz.0 + 1, xyz.1 - 1, xyz.2 - 1),
-        (xyz.0 + 1, xyz.1 - 1, xyz.2),
-        (xyz.0 + 1, xyz.1 - 1, xyz.2 + 1),
-        (xyz.0 + 1, xyz.1, xyz.2 - 1),
-        (xyz.0 + 1, xyz.1, xyz.2),
-        (xyz.0 + 1, xyz.1, xyz.2 + 1),
-        (xyz.0 + 1, xyz.1 + 1, xyz.2 - 1),
-        (xyz.0 + 1, xyz.1 + 1, xyz.2),
-        (xyz.0 + 1, xyz.1 + 1, xyz.2 + 1),
-    ]
-}
-
-fn neighbours_von_neumann_3d(uxyz: (usize, usize, usize)) -> [(isize, isize, isize); 6] {
-    let xyz = (uxyz.0 as isize, uxyz.1 as isize, uxyz.2 as isize);
-    [
-        (xyz.0, xyz.1 - 1, xyz.2),
-        (xyz.0, xyz.1 + 1, xyz.2),
-        (xyz.0 - 1, xyz.1, xyz.2),
-        (xyz.0 + 1, xyz.1, xyz.2),
-        (xyz.0, xyz.1, xyz.2 - 1),
-        (xyz.0, xyz.1, xyz.2 + 1),
-    ]
-}
-
 // during each step we keep track of the changes
 // the new state of the system is represented as
 // a Vec<SysChange>, that can be applied to the AutoSystem3d
@@ -247,10 +1649,10 @@ impl SysChange {
     ) -> Self {
         Self {
             uxyz: (x, y, z),
-            element: Some(Automaton::new(
-                automaton.entity,
-                automaton.life + life_change,
-            )),
+            element: Some(Automaton {
+                life: automaton.life + life_change,
+                ..automaton
+            }),
         }
     }
     pub fn x(&self) -> usize {
@@ -267,65 +1669,447 @@ impl SysChange {
     }
 }
 
-// define rules for the 3d automata
-// if despawn_lower < number of neighbours < despawn_upper
-// then the cube will survive, else life will be reduced
-// by one, if life is already zero, the cube will be despawned
-// if spawn_lower < number of neighbours < spawn_upper
-// and the spot is empty a new cube will spawn
-// note that a life of 0 still results in two possible
-// states: Some(Entity) and None
-#[derive(Debug, Copy, Clone, Resource)]
-pub struct Rules {
-    spawn_lower: usize,
-    spawn_upper: usize,
-    despawn_lower: usize,
-    despawn_upper: usize,
-    life: isize,
+// summary of a single generation step
+// contains the number of cubes that were spawned, despawned or lost
+// a life, see 'AutoSystem3d::last_changes' for the positions
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StepReport {
+    births: usize,
+    deaths: usize,
+    decays: usize,
+    heals: usize,
+    population: usize,
 }
 
-impl Rules {
-    pub fn new(
-        spawn_lower: usize,
-        spawn_upper: usize,
-        despawn_lower: usize,
-        despawn_upper: usize,
-        life: isize,
-    ) -> Self {
-        Self {
-            spawn_lower,
-            spawn_upper,
-            despawn_lower,
-            despawn_upper,
-            life,
+impl StepReport {
+    // keep track of a single cell that changed from state 'old' to 'new'
+    pub(crate) fn count(&mut self, old: u8, new: u8) {
+        match (old, new) {
+            (0, _) => self.births += 1,
+            (_, 0) => self.deaths += 1,
+            _ if new > old => self.heals += 1,
+            _ => self.decays += 1,
         }
     }
-    
-    pub fn spawn_lower(&self) -> usize {
-        self.spawn_lower
+    pub(crate) fn with_population(self, population: usize) -> Self {
+        Self { population, ..self }
     }
-    pub fn spawn_upper(&self) -> usize {
-        self.spawn_upper
+    pub(crate) fn merge(self, other: StepReport) -> StepReport {
+        StepReport {
+            births: self.births + other.births,
+            deaths: self.deaths + other.deaths,
+            decays: self.decays + other.decays,
+            heals: self.heals + other.heals,
+            population: self.population + other.population,
+        }
     }
-    pub fn despawn_lower(&self) -> usize {
-        self.despawn_lower
+    pub fn births(&self) -> usize {
+        self.births
     }
-    pub fn despawn_upper(&self) -> usize {
-        self.despawn_upper
+    pub fn deaths(&self) -> usize {
+        self.deaths
     }
-    pub fn life(&self) -> isize {
-        self.life
+    pub fn decays(&self) -> usize {
+        self.decays
+    }
+    // cubes that regained life, see 'Rules::heal'
+    pub fn heals(&self) -> usize {
+        self.heals
+    }
+    // number of occupied cells after the step
+    pub fn population(&self) -> usize {
+        self.population
     }
 }
-// should correspond to '5-6/5-6/5/M' in standard notation
-impl Default for Rules {
-    fn default() -> Self {
-        Self {
-            spawn_lower: 4,
-            spawn_upper: 7,
-            despawn_lower: 5,
-            despawn_upper: 6,
-            life: 3,
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::species::Ownership;
+
+    fn rules(input: &str) -> Rules {
+        Rules::parse_from_str(input).unwrap()
+    }
+
+    fn rules_with_semantics(input: &str, semantics: Semantics) -> Rules {
+        let mut rules = rules(input);
+        rules.set_semantics(semantics);
+        rules
+    }
+
+    // the positions of all cubes
+    fn cubes(system: &AutoSystem3d) -> Vec<(usize, usize, usize)> {
+        let dims = system.dims();
+        let mut cubes = Vec::new();
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    if system.get_at_xyz((i, j, k)).is_some() {
+                        cubes.push((i, j, k));
+                    }
+                }
+            }
+        }
+        cubes
+    }
+
+    // the state of every cell
+    fn cells(system: &AutoSystem3d) -> Vec<Option<Automaton>> {
+        let dims = system.dims();
+        let mut cells = Vec::with_capacity(dims.max_amount());
+        for i in dims.range_x() {
+            for j in dims.range_y() {
+                for k in dims.range_z() {
+                    cells.push(system.get_at_xyz((i, j, k)));
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn steppings_agree() {
+        let boundaries = [
+            Boundaries::new(Boundary::Dead, Boundary::Mirror, Boundary::Wrap),
+            Boundaries::new(Boundary::Alive, Boundary::Twisted, Boundary::Shifted),
+            Boundaries::new(Boundary::Wrap, Boundary::Dead, Boundary::Mirror),
+        ];
+        // z lengths that are not multiples of the 64 bits of a row, and
+        // lengths that end within a chunk
+        let dims = [
+            SystemDims::new(9, 10, 70),
+            SystemDims::new(7, 8, 130),
+            SystemDims::new(20, 18, 34),
+        ];
+        let steppings = [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental];
+        for input in [
+            "6-8/7/4/M",
+            "0-6/1,3/2/VN",
+            "4-6,d1-9/u1-3,d0/4/M",
+            "5-6/d1-9/3/-y/M",
+            "4-6@0.9,7/5@0.7,6/4@0.01/M",
+        ] {
+            for semantics in [Semantics::Legacy, Semantics::Standard] {
+                let rules = rules_with_semantics(input, semantics);
+                for (dims, boundaries) in dims.iter().zip(boundaries) {
+                    let mut start = AutoSystem3d::new_from_dims(dims);
+                    start.set_boundaries(boundaries);
+                    start.fill_pseudorandom(dims, 0.3, rules.life(), 7);
+                    let mut systems = steppings.map(|stepping| {
+                        let mut system = start.clone();
+                        system.set_stepping(stepping);
+                        system
+                    });
+                    for g in 0..6 {
+                        let reports = systems
+                            .each_mut()
+                            .map(|s| s.step(&rules, dims, true, Noise::new(3, g)));
+                        let context = format!("{input} {semantics} {boundaries:?} generation {g}");
+                        let expected = cells(&systems[0]);
+                        for (report, system) in reports.iter().zip(&systems).skip(1) {
+                            let context = format!("{context} {:?}", system.stepping());
+                            assert_eq!(*report, reports[0], "{context}");
+                            assert_eq!(cells(system), expected, "{context}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn second_order_runs_back() {
+        let rules = rules("6-8/7/4/M");
+        let dims = SystemDims::new(12, 10, 70);
+        let generations = 8;
+        for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+            for boundary in [Boundary::Wrap, Boundary::Dead, Boundary::Mirror] {
+                let mut system = AutoSystem3d::new_from_dims(&dims);
+                system.set_stepping(stepping);
+                system.set_scheme(Scheme::SecondOrder);
+                system.set_boundaries(Boundaries::all(boundary));
+                system.fill_pseudorandom(&dims, 0.3, rules.life(), 11);
+                let initial = cells(&system);
+                for g in 0..generations {
+                    system.step(&rules, &dims, true, Noise::new(0, g));
+                }
+                assert_ne!(cells(&system), initial, "{stepping:?} {boundary:?}");
+                // reversing is the first step back
+                system.reverse();
+                for g in 1..generations {
+                    system.step(&rules, &dims, true, Noise::new(0, g));
+                }
+                assert_eq!(cells(&system), initial, "{stepping:?} {boundary:?}");
+                assert_eq!(system.population(), cubes(&system).len());
+            }
+        }
+    }
+
+    // the same seed gives the same result, whatever the stepping
+    #[test]
+    fn schemes_are_reproducible() {
+        let rules = rules("5-7/6/3/M");
+        let dims = SystemDims::new(12, 10, 70);
+        let mut start = AutoSystem3d::new_from_dims(&dims);
+        start.set_boundaries(Boundaries::all(Boundary::Wrap));
+        start.fill_pseudorandom(&dims, 0.3, rules.life(), 13);
+        let run = |scheme, stepping, seed| {
+            let mut system = start.clone();
+            system.set_scheme(scheme);
+            system.set_stepping(stepping);
+            let reports: Vec<StepReport> = (0..6)
+                .map(|g| system.step(&rules, &dims, true, Noise::new(seed, g)))
+                .collect();
+            (reports, cells(&system))
+        };
+        for scheme in [
+            Scheme::Sequential,
+            Scheme::Asynchronous(Chance::HALF),
+            Scheme::Checkerboard,
+        ] {
+            let expected = run(scheme, Stepping::Cellwise, 5);
+            for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+                assert_eq!(run(scheme, stepping, 5), expected, "{scheme} {stepping:?}");
+            }
+            // only the random order and choice of cells depend on the seed
+            let other = run(scheme, Stepping::Cellwise, 6);
+            assert_eq!(
+                other == expected,
+                scheme == Scheme::Checkerboard,
+                "{scheme}"
+            );
+        }
+    }
+
+    #[test]
+    fn asynchronous_extremes() {
+        let dims = SystemDims::new(12, 10, 70);
+        for input in ["6-8/7/4/M", "4-6@0.9,7/5@0.7,6/4@0.01/M"] {
+            let rules = rules(input);
+            let mut start = AutoSystem3d::new_from_dims(&dims);
+            start.fill_pseudorandom(&dims, 0.3, rules.life(), 17);
+            for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+                let mut systems = [Chance::NEVER, Chance::ALWAYS, Chance::ALWAYS].map(|alpha| {
+                    let mut system = start.clone();
+                    system.set_stepping(stepping);
+                    system.set_scheme(Scheme::Asynchronous(alpha));
+                    system
+                });
+                systems[2].set_scheme(Scheme::Synchronous);
+                for g in 0..4 {
+                    let reports = systems
+                        .each_mut()
+                        .map(|s| s.step(&rules, &dims, true, Noise::new(2, g)));
+                    let context = format!("{input} {stepping:?} generation {g}");
+                    // no cell is updated
+                    assert_eq!(
+                        reports[0],
+                        StepReport::default().with_population(start.population())
+                    );
+                    assert_eq!(cells(&systems[0]), cells(&start), "{context}");
+                    // every cell is updated
+                    assert_eq!(reports[1], reports[2], "{context}");
+                    assert_eq!(cells(&systems[1]), cells(&systems[2]), "{context}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lonely_cube_spreads() {
+        let rules = rules("1/1/2/M");
+        let dims = SystemDims::new(16, 16, 16);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.set_xyz((8, 8, 8), Automaton::new(rules.life()));
+        let report = system.step(&rules, &dims, true, Noise::default());
+        assert_eq!((report.births(), report.deaths()), (26, 1));
+        assert_eq!(report.population(), 26);
+        assert_eq!(system.population(), 26);
+        assert_eq!(system.get_at_xyz((8, 8, 8)), None);
+        assert_eq!(system.get_at_xyz((7, 9, 8)), Some(Automaton::new(0)));
+        assert_eq!(system.last_changes().count(), 27);
+    }
+
+    #[test]
+    fn dims_from_str() {
+        let dims: SystemDims = "128x128X8".parse().unwrap();
+        assert_eq!(dims, SystemDims::new(128, 128, 8));
+        assert_eq!(
+            format!("1x{MAX_EDGE}x1").parse(),
+            Ok(SystemDims::new(1, MAX_EDGE, 1))
+        );
+        for input in [
+            "128x128",
+            "0x16x16",
+            format!("16x{}x16", MAX_EDGE + 1).as_str(),
+            "16xax16",
+        ] {
+            assert!(input.parse::<SystemDims>().is_err(), "{input}");
+        }
+    }
+
+    // a wrapped axis of length 1 or 2 has the same cells on both sides,
+    // so a cell counts its own copies, or the same neighbour twice, as in
+    // an infinite stack of identical layers
+    #[test]
+    fn short_wrapped_axes() {
+        let single = SystemDims::new(4, 4, 1);
+        let mut system = AutoSystem3d::new_from_dims(&single);
+        system.set_xyz((1, 1, 0), Automaton::new(0));
+        assert_eq!(system.count_neighbours_moore((1, 1, 0), &single), 2);
+        assert_eq!(system.count_neighbours_moore((2, 2, 0), &single), 3);
+        assert_eq!(system.count_neighbours_von_neumann((1, 1, 0), &single), 2);
+        assert_eq!(system.count_neighbours_von_neumann((2, 1, 0), &single), 1);
+        let double = SystemDims::new(4, 4, 2);
+        let mut system = AutoSystem3d::new_from_dims(&double);
+        system.set_xyz((1, 1, 0), Automaton::new(0));
+        assert_eq!(system.count_neighbours_moore((1, 1, 0), &double), 0);
+        assert_eq!(system.count_neighbours_moore((1, 1, 1), &double), 2);
+        assert_eq!(system.count_neighbours_moore((2, 2, 1), &double), 2);
+        assert_eq!(system.count_neighbours_moore((2, 2, 0), &double), 1);
+        assert_eq!(system.count_neighbours_von_neumann((1, 1, 1), &double), 2);
+        assert_eq!(system.count_neighbours_von_neumann((2, 1, 1), &double), 0);
+        // every stepping counts the same
+        let rules = rules("5-9/6-8/3/M");
+        for dims in [SystemDims::new(10, 10, 1), SystemDims::new(10, 10, 2)] {
+            let mut start = AutoSystem3d::new_from_dims(&dims);
+            start.fill_pseudorandom(&dims, 0.4, rules.life(), 5);
+            let mut systems =
+                [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental].map(|stepping| {
+                    let mut system = start.clone();
+                    system.set_stepping(stepping);
+                    system
+                });
+            for g in 0..6 {
+                for system in &mut systems {
+                    system.step(&rules, &dims, true, Noise::new(0, g));
+                }
+                assert_eq!(cells(&systems[1]), cells(&systems[0]), "{dims:?} {g}");
+                assert_eq!(cells(&systems[2]), cells(&systems[0]), "{dims:?} {g}");
+            }
+        }
+    }
+
+    #[test]
+    fn memory_estimate() {
+        let dims = SystemDims::new(64, 64, 64);
+        let cells = dims.max_amount();
+        let rules = rules("6-8/7/4/M");
+        let estimate =
+            |stepping, scheme| AutoSystem3d::estimate_memory(&dims, &rules, stepping, scheme);
+        let base = estimate(Stepping::Cellwise, Scheme::Synchronous);
+        assert!((2 * cells..3 * cells).contains(&base));
+        assert!(estimate(Stepping::Incremental, Scheme::Synchronous) > base + 4 * cells);
+        // the copy of the system and the ranks of the cells
+        assert!(estimate(Stepping::Cellwise, Scheme::Checkerboard) > base + cells);
+        assert_eq!(
+            estimate(Stepping::Cellwise, Scheme::Sequential),
+            base + 32 * cells
+        );
+        // the species and the owners of the new state
+        let competing = Rules::competing(Competition::new(
+            vec![rules.clone(), rules.clone()],
+            Neighbours::All,
+            Ownership::Majority,
+        ));
+        let species =
+            AutoSystem3d::estimate_memory(&dims, &competing, Stepping::Bitwise, Scheme::Sequential);
+        assert!(species > base + 2 * cells);
+        assert!(species < base + 3 * cells);
+    }
+
+    #[test]
+    fn emptied_chunks_are_released() {
+        let dims = SystemDims::new(40, 40, 40);
+        for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+            let mut system = AutoSystem3d::new_from_dims(&dims);
+            system.set_stepping(stepping);
+            // the corner of the first chunk, its neighbours fill 8 chunks
+            system.set_xyz((15, 15, 15), Automaton::new(0));
+            assert_eq!(system.data.allocated(), 1);
+            system.step(&rules("1/1/2/M"), &dims, true, Noise::default());
+            assert_eq!(system.population(), 26);
+            assert_eq!(system.data.allocated(), 8, "{stepping:?}");
+            system.step(&rules("26/26/2/M"), &dims, false, Noise::default());
+            assert_eq!(system.population(), 0);
+            assert_eq!(system.data.allocated(), 0, "{stepping:?}");
+        }
+    }
+
+    #[test]
+    fn still_life() {
+        let rules = rules("7/4/2/M");
+        let dims = SystemDims::new(16, 16, 16);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        for b in 0..8 {
+            system.set_xyz((b >> 2 & 1, b >> 1 & 1, b & 1), Automaton::new(0));
+        }
+        let before = cubes(&system);
+        for g in 0..3 {
+            let report = system.step(&rules, &dims, true, Noise::new(0, g));
+            assert_eq!(report, StepReport::default().with_population(8));
+            assert_eq!(system.last_changes().count(), 0);
+        }
+        assert_eq!(cubes(&system), before);
+    }
+
+    #[test]
+    fn decay_and_growth() {
+        let rules = rules("8/1/4/M");
+        let dims = SystemDims::new(16, 16, 16);
+        let mut system = AutoSystem3d::new_from_dims(&dims);
+        system.set_xyz((0, 0, 0), Automaton::new(rules.life()));
+        // spawning needs both ranges in legacy semantics
+        let report = system.step(&rules, &dims, true, Noise::default());
+        assert_eq!(report.decays(), 1);
+        assert_eq!((report.births(), report.deaths()), (0, 0));
+        assert_eq!(system.get_at_xyz((0, 0, 0)), Some(Automaton::new(1)));
+        system.step(&rules, &dims, true, Noise::default());
+        let report = system.step(&rules, &dims, true, Noise::default());
+        assert_eq!((report.deaths(), report.population()), (1, 0));
+        // in standard semantics spawning only needs the spawn range, but
+        // not without growth
+        let rules = rules_with_semantics("8/1/4/M", Semantics::Standard);
+        for (growth, births) in [(false, 0), (true, 26)] {
+            system.clear();
+            system.set_xyz((0, 0, 0), Automaton::new(rules.life()));
+            let report = system.step(&rules, &dims, growth, Noise::default());
+            assert_eq!((report.births(), report.decays()), (births, 1));
+        }
+    }
+
+    #[test]
+    fn heal() {
+        let dims = SystemDims::new(16, 16, 16);
+        for semantics in [Semantics::Legacy, Semantics::Standard] {
+            let mut rules = rules_with_semantics("0-1/26/6/M", semantics);
+            assert_eq!(rules.life(), 4);
+            rules.set_heal(2);
+            for stepping in [Stepping::Cellwise, Stepping::Bitwise, Stepping::Incremental] {
+                let context = format!("{semantics} {stepping:?}");
+                let mut system = AutoSystem3d::new_from_dims(&dims);
+                system.set_stepping(stepping);
+                // a weakened cube next to a full one, both of them within
+                // the survival range, whether weakened cubes count or not
+                system.set_xyz((4, 4, 4), Automaton::new(1));
+                system.set_xyz((4, 4, 5), Automaton::new(4));
+                let report = system.step(&rules, &dims, true, Noise::default());
+                assert_eq!((report.heals(), report.decays()), (1, 0), "{context}");
+                assert_eq!(system.get_at_xyz((4, 4, 4)), Some(Automaton::new(3)));
+                // up to the life of the rules
+                let report = system.step(&rules, &dims, true, Noise::default());
+                assert_eq!(report.heals(), 1, "{context}");
+                assert_eq!(system.get_at_xyz((4, 4, 4)), Some(Automaton::new(4)));
+                let report = system.step(&rules, &dims, true, Noise::default());
+                assert_eq!(report, StepReport::default().with_population(2));
+                assert_eq!(system.get_at_xyz((4, 4, 5)), Some(Automaton::new(4)));
+                // outside of the survival range cubes decay regardless
+                system.set_xyz((4, 4, 4), Automaton::new(1));
+                system.set_xyz((4, 5, 4), Automaton::new(4));
+                let report = system.step(&rules, &dims, true, Noise::default());
+                assert_eq!(report.heals(), 0, "{context}");
+                assert_eq!(system.get_at_xyz((4, 4, 4)), Some(Automaton::new(0)));
+            }
         }
     }
 }
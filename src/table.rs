@@ -0,0 +1,310 @@
+use crate::rules::Neighbourhood;
+use crate::system::MAX_LIFE;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+// isotropic non-totalistic rules: survival and spawning depend on which
+// neighbours are occupied rather than on their number
+// configurations that are mapped onto each other by one of the 48
+// symmetries of the cube form a class, and the rules are given per class
+// in the text format, every class is listed by one of its configurations,
+// i.e. the offsets of all occupied neighbours, e.g.
+//   states 4
+//   neighbourhood M
+//   survive 1,0,0 -1,0,0
+//   spawn 1,0,0 0,1,0 0,0,1
+//   spawn
+// where the last line stands for no occupied neighbours at all
+// empty lines and anything after '#' are ignored
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Table {
+    // where the table came from, e.g. the path of the file
+    source: String,
+    states: isize,
+    neighbourhood: Neighbourhood,
+    // outcome for every configuration, bit i of a configuration is set if
+    // the neighbour at 'neighbourhood.offsets()[i]' is occupied
+    survive: Arc<[bool]>,
+    spawn: Arc<[bool]>,
+    // number of classes listed for survival and spawning
+    classes: (usize, usize),
+}
+
+impl Table {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::parse(path, &text).map_err(|e| format!("{path}: {e}"))
+    }
+    pub fn parse(source: &str, text: &str) -> Result<Self, String> {
+        let mut states = None;
+        let mut neighbourhood = None;
+        // line number and configuration of every listed class
+        let mut survive = Vec::new();
+        let mut spawn = Vec::new();
+        for (l, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "" => {}
+                "states" => {
+                    let s = value
+                        .parse::<isize>()
+                        .ok()
+                        .filter(|s| (2..=MAX_LIFE + 2).contains(s))
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: expected between 2 and {} states",
+                                l + 1,
+                                MAX_LIFE + 2
+                            )
+                        })?;
+                    states = Some(s);
+                }
+                "neighbourhood" => {
+                    let n = Neighbourhood::parse_from_str(value)
+                        .filter(|n| {
+                            matches!(
+                                n,
+                                Neighbourhood::Moore(1)
+                                    | Neighbourhood::VonNeumann(1)
+                                    | Neighbourhood::FaceEdge
+                            )
+                        })
+                        .ok_or_else(|| {
+                            format!("line {}: expected the M, VN or N18 neighbourhood", l + 1)
+                        })?;
+                    neighbourhood = Some(n);
+                }
+                "survive" => survive.push((l, value)),
+                "spawn" => spawn.push((l, value)),
+                _ => {
+                    return Err(format!(
+                        "line {}: expected 'states', 'neighbourhood', 'survive' or 'spawn'",
+                        l + 1
+                    ))
+                }
+            }
+        }
+        let states = states.ok_or("the number of states is missing")?;
+        let neighbourhood = neighbourhood.ok_or("the neighbourhood is missing")?;
+        let offsets = neighbourhood.offsets();
+        let symmetries = symmetries(&offsets);
+        let outcome = |list: &[(usize, &str)]| -> Result<(Arc<[bool]>, usize), String> {
+            let mut table = vec![false; 1 << offsets.len()];
+            let mut classes = HashSet::new();
+            for &(l, value) in list {
+                let mut configuration = 0usize;
+                for token in value.split_whitespace() {
+                    let offset = parse_offset(token)
+                        .and_then(|o| offsets.iter().position(|&p| p == o))
+                        .ok_or_else(|| format!("line {}: '{token}' is not a neighbour", l + 1))?;
+                    configuration |= 1 << offset;
+                }
+                // the class is identified by its smallest configuration
+                let mut smallest = configuration;
+                for symmetry in &symmetries {
+                    let image = permute(configuration, symmetry);
+                    table[image] = true;
+                    smallest = smallest.min(image);
+                }
+                classes.insert(smallest);
+            }
+            Ok((table.into(), classes.len()))
+        };
+        let (survive, survive_classes) = outcome(&survive)?;
+        let (spawn, spawn_classes) = outcome(&spawn)?;
+        Ok(Self {
+            source: source.to_string(),
+            states,
+            neighbourhood,
+            survive,
+            spawn,
+            classes: (survive_classes, spawn_classes),
+        })
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn states(&self) -> isize {
+        self.states
+    }
+    pub fn neighbourhood(&self) -> &Neighbourhood {
+        &self.neighbourhood
+    }
+    // outcome for every configuration, see 'configuration_weights'
+    pub fn survive(&self) -> &Arc<[bool]> {
+        &self.survive
+    }
+    pub fn spawn(&self) -> &Arc<[bool]> {
+        &self.spawn
+    }
+    // number of classes listed for survival and spawning
+    pub fn classes(&self) -> (usize, usize) {
+        self.classes
+    }
+    // weights of the neighbours, so that the neighbour count of a cell
+    // is its configuration
+    pub fn configuration_weights(&self) -> Vec<usize> {
+        (0..self.neighbourhood.size()).map(|i| 1 << i).collect()
+    }
+}
+
+fn parse_offset(token: &str) -> Option<(isize, isize, isize)> {
+    let c: Vec<isize> = token
+        .split(',')
+        .map(|c| c.parse().ok())
+        .collect::<Option<_>>()?;
+    match c[..] {
+        [x, y, z] => Some((x, y, z)),
+        _ => None,
+    }
+}
+
+// the 48 symmetries of the cube as permutations of the offsets, i.e. the
+// offset at position i is mapped onto the offset at position s[i]
+// all neighbourhoods of tables are symmetric, so every image is an offset
+fn symmetries(offsets: &[(isize, isize, isize)]) -> Vec<Vec<usize>> {
+    let axes = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+    let mut symmetries = Vec::new();
+    for axes in axes {
+        for signs in 0..8 {
+            let sign = |i: usize| if signs >> i & 1 == 1 { -1 } else { 1 };
+            let map = offsets
+                .iter()
+                .map(|o| {
+                    let c = [o.0, o.1, o.2];
+                    let image = (
+                        sign(0) * c[axes[0]],
+                        sign(1) * c[axes[1]],
+                        sign(2) * c[axes[2]],
+                    );
+                    offsets
+                        .iter()
+                        .position(|&p| p == image)
+                        .expect("asymmetric neighbourhood")
+                })
+                .collect();
+            symmetries.push(map);
+        }
+    }
+    symmetries
+}
+
+fn permute(configuration: usize, symmetry: &[usize]) -> usize {
+    symmetry
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| configuration >> i & 1 == 1)
+        .fold(0, |image, (_, &j)| image | 1 << j)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(
+        offsets: &[(isize, isize, isize)],
+        occupied: &[(isize, isize, isize)],
+    ) -> usize {
+        occupied
+            .iter()
+            .map(|o| 1 << offsets.iter().position(|p| p == o).unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn distinct_symmetries() {
+        for n in ["M", "VN", "N18"] {
+            let offsets = Neighbourhood::parse_from_str(n).unwrap().offsets();
+            let mut symmetries = symmetries(&offsets);
+            assert_eq!(symmetries.len(), 48);
+            for s in &symmetries {
+                let mut images = s.clone();
+                images.sort();
+                assert!(images.iter().copied().eq(0..offsets.len()), "{n}");
+            }
+            symmetries.sort();
+            symmetries.dedup();
+            assert_eq!(symmetries.len(), 48, "{n}");
+        }
+    }
+
+    #[test]
+    fn classes() {
+        let table = Table::parse(
+            "test",
+            "states 3\nneighbourhood M\nsurvive 1,0,0\nsurvive 0,0,-1 # again\nspawn 1,0,0 0,1,0\nspawn",
+        )
+        .unwrap();
+        assert_eq!(table.states(), 3);
+        assert_eq!(table.classes(), (1, 2));
+        let offsets = table.neighbourhood().offsets();
+        let c = |occupied: &[(isize, isize, isize)]| configuration(&offsets, occupied);
+        // the six faces
+        assert_eq!(table.survive().iter().filter(|&&s| s).count(), 6);
+        for face in [
+            (1, 0, 0),
+            (-1, 0, 0),
+            (0, 1, 0),
+            (0, -1, 0),
+            (0, 0, 1),
+            (0, 0, -1),
+        ] {
+            assert!(table.survive()[c(&[face])]);
+        }
+        assert!(!table.survive()[c(&[(1, 1, 0)])]);
+        assert!(!table.survive()[0]);
+        // the twelve pairs of adjacent faces and no neighbours at all
+        assert_eq!(table.spawn().iter().filter(|&&s| s).count(), 13);
+        assert!(table.spawn()[0]);
+        assert!(table.spawn()[c(&[(0, 0, -1), (0, -1, 0)])]);
+        assert!(table.spawn()[c(&[(-1, 0, 0), (0, 0, 1)])]);
+        assert!(!table.spawn()[c(&[(1, 0, 0), (-1, 0, 0)])]);
+        assert!(!table.spawn()[c(&[(1, 0, 0)])]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| Table::parse("test", text).unwrap_err();
+        assert_eq!(error("neighbourhood M"), "the number of states is missing");
+        assert_eq!(error("states 2"), "the neighbourhood is missing");
+        assert!(error("states 1").starts_with("line 1: expected between 2 and"));
+        assert_eq!(
+            error("states 2\nneighbourhood M2"),
+            "line 2: expected the M, VN or N18 neighbourhood"
+        );
+        assert_eq!(
+            error("states 2\nneighbourhood M\nbirth 1,0,0"),
+            "line 3: expected 'states', 'neighbourhood', 'survive' or 'spawn'"
+        );
+        // offsets that are not part of the neighbourhood
+        assert_eq!(
+            error("states 2\nneighbourhood VN\nsurvive 1,0,0 1,1,0"),
+            "line 3: '1,1,0' is not a neighbour"
+        );
+        assert_eq!(
+            error("states 2\nneighbourhood N18\n\nspawn 1,1,1"),
+            "line 4: '1,1,1' is not a neighbour"
+        );
+        assert_eq!(
+            error("states 2\nneighbourhood M\nspawn 0,0,0"),
+            "line 3: '0,0,0' is not a neighbour"
+        );
+        assert_eq!(
+            error("states 2\nneighbourhood M\nspawn 2,0,0"),
+            "line 3: '2,0,0' is not a neighbour"
+        );
+        assert_eq!(
+            error("states 2\nneighbourhood M\nspawn 1,0"),
+            "line 3: '1,0' is not a neighbour"
+        );
+    }
+}
@@ -1,25 +1,152 @@
 use crate::{
     calc_spawn_coords,
     cli::{Cli, LightMode},
-    rel_density,
-    rules::{Neighbourhood, Rules},
-    system::{AutoSystem3d, Automaton, SysChange},
+    cube_density,
+    hashlife::HashLife,
+    isizify3, rel_density,
+    rules::{Rules, PRESETS},
+    sparse::{Position, SparseSystem3d},
+    system::{AutoSystem3d, Automaton, Noise, Scheme},
     GlobalData, GlobalStatic, SystemTimer, ALPHA, BLOOM, CUBE_SIZE,
 };
-use bevy::prelude::*;
+use bevy::{core_pipeline::bloom::Bloom, prelude::*};
 use bevy_panorbit_camera::PanOrbitCamera;
 use colorgrad::Gradient;
 use rand::prelude::*;
-use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
 use xorwowgen::xorwow64::XorA;
 
+// keeps track of the entities that visualize the automata
+// the simulation itself does not know anything about entities
+#[derive(Default, Resource)]
+pub struct CubeEntities {
+    entities: HashMap<(isize, isize, isize), Entity>,
+}
+
+impl CubeEntities {
+    pub fn insert(&mut self, ixyz: (isize, isize, isize), entity: Entity) {
+        self.entities.insert(ixyz, entity);
+    }
+    pub fn remove(&mut self, ixyz: (isize, isize, isize)) -> Option<Entity> {
+        self.entities.remove(&ixyz)
+    }
+    pub fn get(&self, ixyz: (isize, isize, isize)) -> Option<Entity> {
+        self.entities.get(&ixyz).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+    // forget all entities, e.g. in order to despawn them
+    pub fn drain(&mut self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.drain().map(|(_, e)| e)
+    }
+}
+
+// create mesh and material for cubes spawned in the current generation,
+// one pair per species, see 'GlobalStatic::gradients'
+fn cube_handles(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    global_data: &GlobalData,
+    global_stat: &GlobalStatic,
+    cli: &Cli,
+) -> Vec<(Handle<Mesh>, Handle<StandardMaterial>)> {
+    let mesh_handle = meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE));
+    global_stat
+        .gradients()
+        .iter()
+        .map(|gradient| {
+            // get color of current generation
+            let c = gradient.reflect_at((global_data.generation() as f32) / 20.0);
+            // set emission if bloom mode is chosen
+            let mat_handle = match cli.light_mode {
+                LightMode::Bloom => materials.add(StandardMaterial {
+                    emissive: LinearRgba::new(c.r * BLOOM, c.g * BLOOM, c.b * BLOOM, ALPHA),
+                    alpha_mode: AlphaMode::Add,
+                    ..default()
+                }),
+                LightMode::Normal => materials.add(Color::srgb(c.r, c.g, c.b)),
+            };
+            (mesh_handle.clone(), mat_handle)
+        })
+        .collect()
+}
+
+// spawn one entity per given position, with the handles of the given
+// species
+// in very large systems rendering degrades gracefully: once 'limit'
+// entities exist, further cubes are simulated but not shown
+fn spawn_cubes(
+    commands: &mut Commands,
+    cubes: &mut CubeEntities,
+    positions: &[(Position, u8)],
+    handles: &[(Handle<Mesh>, Handle<StandardMaterial>)],
+    global_stat: &GlobalStatic,
+    limit: usize,
+) {
+    let room = limit.saturating_sub(cubes.len());
+    for &(ixyz, species) in positions.iter().take(room) {
+        let sc = calc_spawn_coords(ixyz, &global_stat.dims());
+        let handles = &handles[species as usize];
+        let id = commands
+            .spawn((
+                Mesh3d(handles.0.clone()),
+                MeshMaterial3d(handles.1.clone()),
+                Transform::from_xyz(sc.0, sc.1, sc.2),
+            ))
+            .id();
+        cubes.insert(ixyz, id);
+    }
+}
+
+// positions and species of the cubes spawned by a bounded system
+// if species compete, the cubes are handed over to the species seeded in
+// their region, see 'Competition::region'
+fn claim_all(
+    sys3d: &mut AutoSystem3d,
+    positions: &[(usize, usize, usize)],
+    rules: &Rules,
+) -> Vec<(Position, u8)> {
+    let dims = sys3d.dims();
+    positions
+        .iter()
+        .map(|&(i, j, k)| {
+            let species = match rules.competition() {
+                Some(competition) => {
+                    let at = competition.seed(i, &dims);
+                    sys3d.set_xyz((i, j, k), at);
+                    at.species()
+                }
+                None => 0,
+            };
+            (isizify3(i, j, k), species)
+        })
+        .collect()
+}
+
+// shrink cubes in order to visualize aging
+fn cube_scale(at: Automaton, rules: &Rules) -> Vec3 {
+    let max_life = match rules.competition() {
+        Some(competition) => competition.rules(at.species()).life(),
+        None => rules.life(),
+    };
+    Vec3::splat(0.75f32.powi((max_life - at.life()).max(0) as i32))
+}
+
+// either a bounded or an unbounded system is present, see 'main'
+#[allow(clippy::too_many_arguments)]
 pub fn update_system(
-    par_com: ParallelCommands,
+    mut commands: Commands,
+    mut cubes: ResMut<CubeEntities>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut sys3d: ResMut<AutoSystem3d>,
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
     mut config: ResMut<SystemTimer>,
+    mut transforms: Query<&mut Transform>,
     time: Res<Time>,
     rules: Res<Rules>,
     mut global_data: ResMut<GlobalData>,
@@ -27,124 +154,88 @@ pub fn update_system(
     cli: Res<Cli>,
 ) {
     config.timer.tick(time.delta());
-    if config.timer.finished() && !config.stopped {
-        // get color of current generation
-        let c = global_stat
-            .gradient()
-            .reflect_at((global_data.generation() as f32) / 20.0);
-        let mesh_handle = meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE));
-        // set emission if bloom mode is chosen
-        let mat_handle = match cli.light_mode {
-            LightMode::Bloom => materials.add(StandardMaterial {
-                emissive: LinearRgba::new(c.r * BLOOM, c.g * BLOOM, c.b * BLOOM, ALPHA),
-                alpha_mode: AlphaMode::Add,
-                ..default()
-            }),
-            LightMode::Normal => materials.add(Color::srgb(c.r, c.g, c.b)),
+    // reversing the direction of time takes effect right away, even if
+    // the system is paused, by swapping the current and the previous
+    // generation, which counts as a step in the new direction
+    let reversal = global_data.take_reversal();
+    if reversal || (config.timer.finished() && !config.stopped) {
+        // the number of cubes of every competing species
+        let mut census = None;
+        let (report, changes): (_, Vec<(Position, Option<Automaton>)>) = match (sys3d, sparse) {
+            (Some(mut sys3d), _) => {
+                let report = match reversal {
+                    true => sys3d.reverse(),
+                    false => sys3d.step(
+                        rules.as_ref(),
+                        &global_stat.dims(),
+                        global_data.growth(),
+                        global_data.noise(),
+                    ),
+                };
+                census = rules
+                    .competition()
+                    .map(|competition| sys3d.census(competition.species().len()));
+                let changes = sys3d
+                    .last_changes()
+                    .map(|c| (isizify3(c.x(), c.y(), c.z()), c.element()))
+                    .collect();
+                (report, changes)
+            }
+            (None, Some(mut sparse)) => {
+                let report = match reversal {
+                    true => sparse.reverse(),
+                    false => sparse.step(rules.as_ref(), global_data.growth(), global_data.noise()),
+                };
+                (report, sparse.last_changes().collect())
+            }
+            (None, None) => return,
         };
-        // thread safe smart pointer for the changes that define
-        // the new system state, preallocate memory to optimize speed
-        let changes = Arc::new(Mutex::new(Vec::<SysChange>::with_capacity(
-            global_stat.max_amount,
-        )));
-        // keep track of the number of living cubes
-        let am_counter = Arc::new(Mutex::new(0isize));
-        // concurrently iterate over the system
-        global_stat
-            .dims()
-            .range_x()
-            .into_par_iter()
-            .for_each(|i: usize| {
-                // thread local changes, merged after each thread has finished
-                // preallocate space to optimize speed
-                let mut thread_local_changes =
-                    Vec::<SysChange>::with_capacity(global_stat.max_amount_per_thread);
-                // keep track of spawned/despawned cubes per thread
-                // note that within a single thread 'despawned' can be higher
-                // than 'spawned'
-                let mut spawned = 0isize;
-                let mut despawned = 0isize;
-                // thread local iteration
-                for j in global_stat.dims().range_y() {
-                    for k in global_stat.dims().range_z() {
-                        let uxyz = (i, j, k); // the 'u' stands for 'unsigned'
-                                              // count neighbours and apply rules
-                        let n = match rules.neighbourhood() {
-                            Neighbourhood::Moore => {
-                                sys3d.count_neighbours_moore(uxyz, &global_stat.dims)
-                            }
-                            Neighbourhood::VonNeumann => {
-                                sys3d.count_neighbours_von_neumann(uxyz, &global_stat.dims)
-                            }
-                        };
-                        if rules.check_despawn(n) {
-                            if let Some(at) = sys3d.get_at_xyz(uxyz) {
-                                if at.life() <= 0 {
-                                    // despawn if life is already at zero
-                                    thread_local_changes.push(SysChange::empty(i, j, k));
-                                    par_com.command_scope(|mut commands| {
-                                        commands.entity(at.entity()).despawn();
-                                    });
-                                    despawned += 1;
-                                } else if at.life() > 0 {
-                                    // if life is larger than zero, reduce it by one
-                                    thread_local_changes
-                                        .push(SysChange::change_life(i, j, k, at, -1));
-                                    // shrink cube in order to visualize aging
-                                    par_com.command_scope(|mut commands| {
-                                        commands
-                                            .entity(at.entity())
-                                            .entry::<Transform>()
-                                            .and_modify(|mut t| {
-                                                t.scale *= 0.75;
-                                            });
-                                    });
-                                }
-                            }
-                        } else if rules.check_spawn(n)
-                            && global_data.growth()
-                            && sys3d.get_at_xyz(uxyz).is_none()
-                        {
-                            // spawn cube if spot is empty and neighbour count
-                            // is within specified range
-                            let sc = calc_spawn_coords(uxyz, &global_stat.dims());
-                            let id = par_com.command_scope(|mut commands| {
-                                commands
-                                    .spawn((
-                                        Mesh3d(mesh_handle.clone()),
-                                        MeshMaterial3d(mat_handle.clone()),
-                                        Transform::from_xyz(sc.0, sc.1, sc.2),
-                                    ))
-                                    .id()
-                            });
-                            spawned += 1;
-                            thread_local_changes.push(SysChange::spawn(
-                                i,
-                                j,
-                                k,
-                                Automaton::new(id, rules.life()),
-                            ));
-                        }
+        // synchronise visuals with the new state
+        let mut births = Vec::with_capacity(report.births());
+        for (ixyz, element) in changes {
+            match (element, cubes.get(ixyz)) {
+                (None, _) => {
+                    if let Some(e) = cubes.remove(ixyz) {
+                        commands.entity(e).despawn();
+                    }
+                }
+                (Some(at), Some(e)) => {
+                    if let Ok(mut t) = transforms.get_mut(e) {
+                        t.scale = cube_scale(at, &rules);
                     }
                 }
-                // merge changes
-                let mut chg = changes.lock().unwrap();
-                chg.append(&mut thread_local_changes);
-                // balance of spawned and despawned
-                let mut cnt = am_counter.lock().unwrap();
-                *cnt += spawned - despawned;
-            });
-        let all_chg = changes.lock().unwrap();
-        // apply all changes to the system to finally create the new state
-        sys3d.apply_changes(&*all_chg);
-        let cnt = am_counter.lock().unwrap();
+                (Some(at), None) => births.push((ixyz, at.species())),
+            }
+        }
+        let handles = cube_handles(
+            &mut meshes,
+            &mut materials,
+            &global_data,
+            &global_stat,
+            &cli,
+        );
+        spawn_cubes(
+            &mut commands,
+            &mut cubes,
+            &births,
+            &handles,
+            &global_stat,
+            cli.max_cubes,
+        );
         // keep track of currently living cubes
-        global_data.increase(*cnt);
+        global_data.increase(report.births() as isize - report.deaths() as isize);
         eprint!(
-            "amount: {:012}, density: {:4.3}\r",
+            "amount: {:012}, density: {:4.3}",
             global_data.amount(),
-            rel_density(global_stat.dims.x(), global_data.amount())
+            rel_density(&global_stat.dims(), global_data.amount())
         );
+        match census {
+            Some(census) => {
+                let census: Vec<String> = census.iter().map(|n| n.to_string()).collect();
+                eprint!(", species: {}\r", census.join(" / "));
+            }
+            None => eprint!("\r"),
+        }
         // avoid general overpopulation and sparseness
         if global_data.amount() > global_stat.maximum() {
             global_data.unset_growth();
@@ -153,142 +244,252 @@ pub fn update_system(
         }
         // keep track of generations
         global_data.advance_gen();
+        // stop once the initial state is back
+        if global_data.backwards() && global_data.generation() == 0 {
+            config.stopped = true;
+            eprintln!(
+                "\nreached generation 0, time runs backwards - press 't' and 'space' to go on"
+            );
+        }
+    }
+}
+
+// reverse the direction of time at keystroke, only second-order systems
+// can be run backwards
+pub fn reverse_time(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut global_data: ResMut<GlobalData>,
+    rules: Res<Rules>,
+    cli: Res<Cli>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyT) {
+        return;
     }
+    if cli.update != Scheme::SecondOrder || rules.blocks().is_some() {
+        eprintln!("\ntime can only be reversed with '--update second-order'");
+        return;
+    }
+    global_data.request_reversal();
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_pseudorandom_core(
     mut commands: Commands,
+    mut cubes: ResMut<CubeEntities>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut sys3d: ResMut<AutoSystem3d>,
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
     mut global_data: ResMut<GlobalData>,
     rules: Res<Rules>,
     glstat: Res<GlobalStatic>,
     cli: Res<Cli>,
 ) {
-    let c = glstat
-        .gradient()
-        .reflect_at((global_data.generation() as f32) / 20.0);
-    let mesh_handle = meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE));
-    let mat_handle = match cli.light_mode {
-        LightMode::Bloom => materials.add(StandardMaterial {
-            emissive: LinearRgba::new(c.r * BLOOM, c.g * BLOOM, c.b * BLOOM, ALPHA),
-            alpha_mode: AlphaMode::Add,
-            ..default()
-        }),
-        LightMode::Normal => materials.add(Color::srgb(c.r, c.g, c.b)),
-    };
-    let mut changes = Vec::<SysChange>::new();
-    let mut count = 0isize;
+    let handles = cube_handles(&mut meshes, &mut materials, &global_data, &glstat, &cli);
     let mut rng = XorA::seed_from_u64(global_data.seed());
-    for i in glstat.dims().core_range_x(cli.fraction) {
-        for j in glstat.dims().core_range_y(cli.fraction) {
-            for k in glstat.dims().core_range_z(cli.fraction) {
-                if rng.gen_bool(cli.core_density) && sys3d.get_at_xyz((i, j, k)).is_none() {
-                    count += 1;
-                    let coords = calc_spawn_coords((i, j, k), &glstat.dims());
-                    let id = commands
-                        .spawn((
-                            Mesh3d(mesh_handle.clone()),
-                            MeshMaterial3d(mat_handle.clone()),
-                            Transform::from_xyz(coords.0, coords.1, coords.2),
-                        ))
-                        .id();
-                    changes.push(SysChange::spawn(i, j, k, Automaton::new(id, rules.life())));
-                }
-            }
+    let spawned = match (sys3d, sparse) {
+        (Some(mut sys3d), _) => {
+            let spawned = sys3d.fill_pseudorandom_core(
+                &glstat.dims(),
+                cli.fraction,
+                cli.core_density,
+                rules.life(),
+                &mut rng,
+            );
+            claim_all(&mut sys3d, &spawned, &rules)
         }
-    }
-    sys3d.apply_changes(&changes);
-    global_data.increase(count);
+        (None, Some(mut sparse)) => sparse
+            .fill_pseudorandom_core(
+                &glstat.dims(),
+                cli.fraction,
+                cli.core_density,
+                rules.life(),
+                &mut rng,
+            )
+            .into_iter()
+            .map(|ixyz| (ixyz, 0))
+            .collect(),
+        (None, None) => return,
+    };
+    spawn_cubes(
+        &mut commands,
+        &mut cubes,
+        &spawned,
+        &handles,
+        &glstat,
+        cli.max_cubes,
+    );
+    global_data.increase(spawned.len() as isize);
     global_data.set_seed(rng.next_u64());
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_pseudorandom_full(
-    par_com: ParallelCommands,
+    mut commands: Commands,
+    mut cubes: ResMut<CubeEntities>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut sys3d: ResMut<AutoSystem3d>,
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
     mut global_data: ResMut<GlobalData>,
     rules: Res<Rules>,
     glstat: Res<GlobalStatic>,
     cli: Res<Cli>,
 ) {
-    let c = glstat
-        .gradient()
-        .reflect_at((global_data.generation() as f32) / 20.0);
-    let mesh_handle = meshes.add(Cuboid::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE));
-    let mat_handle = match cli.light_mode {
-        LightMode::Bloom => materials.add(StandardMaterial {
-            emissive: LinearRgba::new(c.r * BLOOM, c.g * BLOOM, c.b * BLOOM, ALPHA),
-            alpha_mode: AlphaMode::Add,
-            ..default()
-        }),
-        LightMode::Normal => materials.add(Color::srgb(c.r, c.g, c.b)),
+    let handles = cube_handles(&mut meshes, &mut materials, &global_data, &glstat, &cli);
+    let Some(spawned) = fill_full(sys3d, sparse, &glstat, &cli, &rules, &mut global_data) else {
+        return;
     };
-    let changes = Arc::new(Mutex::new(Vec::<SysChange>::new()));
-    let am_count = Arc::new(Mutex::new(0isize));
+    spawn_cubes(
+        &mut commands,
+        &mut cubes,
+        &spawned,
+        &handles,
+        &glstat,
+        cli.max_cubes,
+    );
+}
 
-    glstat
-        .dims()
-        .range_x()
-        .into_par_iter()
-        .for_each(|i: usize| {
-            let mut local_count = 0;
-            let mut local_changes = Vec::<SysChange>::new();
-            let mut rng = XorA::seed_from_u64(
-                global_data
-                    .seed()
-                    .wrapping_add(i.wrapping_mul(999999999) as u64),
+// populate the whole system pseudorandomly, returns the positions and
+// species of the new cubes, or None if there is no system
+fn fill_full(
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
+    glstat: &GlobalStatic,
+    cli: &Cli,
+    rules: &Rules,
+    global_data: &mut GlobalData,
+) -> Option<Vec<(Position, u8)>> {
+    let spawned = match (sys3d, sparse) {
+        (Some(mut sys3d), _) => {
+            let spawned = sys3d.fill_pseudorandom(
+                &glstat.dims(),
+                cli.density,
+                rules.life(),
+                global_data.seed(),
             );
-            for j in glstat.dims().range_y() {
-                for k in glstat.dims().range_z() {
-                    if rng.gen_bool(cli.density) && sys3d.get_at_xyz((i, j, k)).is_none() {
-                        local_count += 1;
-                        let coords = calc_spawn_coords((i, j, k), &glstat.dims());
-                        let id = par_com.command_scope(|mut commands| {
-                            commands
-                                .spawn((
-                                    Mesh3d(mesh_handle.clone()),
-                                    MeshMaterial3d(mat_handle.clone()),
-                                    Transform::from_xyz(coords.0, coords.1, coords.2),
-                                ))
-                                .id()
-                        });
-                        local_changes.push(SysChange::spawn(
-                            i,
-                            j,
-                            k,
-                            Automaton::new(id, rules.life()),
-                        ));
-                    }
-                }
-            }
-            let mut chg = changes.lock().unwrap();
-            chg.append(&mut local_changes);
-            let mut cnt = am_count.lock().unwrap();
-            *cnt += local_count;
-        });
-
-    let chg = changes.lock().unwrap();
-    sys3d.apply_changes(&*chg);
-    let cnt = am_count.lock().unwrap();
-    global_data.increase(*cnt);
+            claim_all(&mut sys3d, &spawned, rules)
+        }
+        (None, Some(mut sparse)) => sparse
+            .fill_pseudorandom(
+                &glstat.dims(),
+                cli.density,
+                rules.life(),
+                global_data.seed(),
+            )
+            .into_iter()
+            .map(|ixyz| (ixyz, 0))
+            .collect(),
+        (None, None) => return None,
+    };
+    global_data.increase(spawned.len() as isize);
 
     let mut rng = XorA::seed_from_u64(global_data.seed());
     for _ in 0..64 {
         rng.next_u64();
     }
     global_data.set_seed(rng.next_u64());
+    Some(spawned)
+}
+
+// jump ahead by '--advance' generations, runs once after the initial fill
+// an unbounded system uses the HashLife engine, whose universe has no
+// walls either, a bounded system is stepped one generation at a time,
+// since cubes meet the walls there (or themselves, if they wrap around)
+// chance can not be memoised, so stochastic rules and update schemes
+// other than the synchronous one are stepped one generation at a time
+// in an unbounded system as well, just like block rules, which do not
+// count neighbours at all, and competing species, which HashLife does
+// not tell apart
+#[allow(clippy::too_many_arguments)]
+pub fn jump_ahead(
+    mut commands: Commands,
+    mut cubes: ResMut<CubeEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
+    mut global_data: ResMut<GlobalData>,
+    rules: Res<Rules>,
+    glstat: Res<GlobalStatic>,
+    cli: Res<Cli>,
+) {
+    if cli.advance == 0 {
+        return;
+    }
+    let dims = glstat.dims();
+    let one_by_one = rules.is_stochastic()
+        || cli.update != Scheme::Synchronous
+        || rules.blocks().is_some()
+        || rules.competition().is_some();
+    let cells: Vec<(Position, Automaton)> = match (sys3d, sparse) {
+        (Some(mut sys3d), _) => {
+            for g in 0..cli.advance {
+                let noise = Noise::new(global_data.seed(), global_data.generation() as u64 + g);
+                sys3d.step(&rules, &dims, true, noise);
+            }
+            dims.range_x()
+                .flat_map(|i| {
+                    dims.range_y()
+                        .flat_map(move |j| dims.range_z().map(move |k| (i, j, k)))
+                })
+                .filter_map(|(i, j, k)| {
+                    sys3d
+                        .get_at_xyz((i, j, k))
+                        .map(|at| (isizify3(i, j, k), at))
+                })
+                .collect()
+        }
+        (None, Some(mut sparse)) => {
+            if one_by_one {
+                for g in 0..cli.advance {
+                    let noise = Noise::new(global_data.seed(), global_data.generation() as u64 + g);
+                    sparse.step(&rules, true, noise);
+                }
+            } else {
+                let mut hashlife = HashLife::from_sparse(&rules, &sparse);
+                hashlife.advance(cli.advance);
+                *sparse = hashlife.to_sparse();
+            }
+            sparse.cells().collect()
+        }
+        (None, None) => return,
+    };
+    for e in cubes.drain() {
+        commands.entity(e).despawn();
+    }
+    global_data.advance_gens(cli.advance as usize);
+    let handles = cube_handles(&mut meshes, &mut materials, &global_data, &glstat, &cli);
+    for (ixyz, at) in cells.iter().take(cli.max_cubes) {
+        let sc = calc_spawn_coords(*ixyz, &dims);
+        let handles = &handles[at.species() as usize];
+        let id = commands
+            .spawn((
+                Mesh3d(handles.0.clone()),
+                MeshMaterial3d(handles.1.clone()),
+                Transform::from_xyz(sc.0, sc.1, sc.2).with_scale(cube_scale(*at, &rules)),
+            ))
+            .id();
+        cubes.insert(*ixyz, id);
+    }
+    let amount = global_data.amount();
+    global_data.increase(cells.len() as isize - amount);
+    eprintln!(
+        "advanced by {} generations, population: {}",
+        cli.advance,
+        cells.len()
+    );
 }
 
 // spawn (pseudo)random cubes at keystroke
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_new_at_keystroke(
-    par_com: ParallelCommands,
     commands: Commands,
+    cubes: ResMut<CubeEntities>,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
-    sys3d: ResMut<AutoSystem3d>,
+    sys3d: Option<ResMut<AutoSystem3d>>,
+    sparse: Option<ResMut<SparseSystem3d>>,
     global_data: ResMut<GlobalData>,
     rules: Res<Rules>,
     glstat: Res<GlobalStatic>,
@@ -297,10 +498,12 @@ pub fn spawn_new_at_keystroke(
 ) {
     if keyboard.just_pressed(KeyCode::KeyN) {
         spawn_pseudorandom_full(
-            par_com,
+            commands,
+            cubes,
             meshes,
             materials,
             sys3d,
+            sparse,
             global_data,
             rules,
             glstat,
@@ -309,9 +512,11 @@ pub fn spawn_new_at_keystroke(
     } else if keyboard.just_pressed(KeyCode::KeyM) {
         spawn_pseudorandom_core(
             commands,
+            cubes,
             meshes,
             materials,
             sys3d,
+            sparse,
             global_data,
             rules,
             glstat,
@@ -320,6 +525,87 @@ pub fn spawn_new_at_keystroke(
     }
 }
 
+// switch to the next preset at keystroke, the system is cleared and
+// filled again according to the preset
+#[allow(clippy::too_many_arguments)]
+pub fn cycle_preset(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut cubes: ResMut<CubeEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sys3d: Option<ResMut<AutoSystem3d>>,
+    mut sparse: Option<ResMut<SparseSystem3d>>,
+    mut global_data: ResMut<GlobalData>,
+    mut rules: ResMut<Rules>,
+    mut glstat: ResMut<GlobalStatic>,
+    mut cli: ResMut<Cli>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut lights: Query<&mut DirectionalLight>,
+    mut cameras: Query<(Entity, &mut Camera), With<PanOrbitCamera>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyP) {
+        return;
+    }
+    if rules.competition().is_some() {
+        eprintln!("\npresets can not be cycled while species compete");
+        return;
+    }
+    let next = cli
+        .preset
+        .and_then(|current| PRESETS.iter().position(|p| p == current))
+        .map_or(0, |i| (i + 1) % PRESETS.len());
+    let preset = &PRESETS[next];
+    cli.apply_preset(preset, |_| true);
+    *rules = cli.rules();
+    let dims = glstat.dims();
+    *glstat = GlobalStatic::new(
+        cli.color_gradient.gradient(),
+        dims,
+        cube_density(&dims, cli.minimum),
+        cube_density(&dims, cli.maximum),
+    );
+    // light
+    let (illuminance, ambi) = cli.light_mode.intensity();
+    for mut light in lights.iter_mut() {
+        light.illuminance = illuminance;
+    }
+    ambient_light.brightness = ambi;
+    commands.insert_resource(cli.light_mode.clear_color());
+    for (entity, mut camera) in cameras.iter_mut() {
+        camera.hdr = cli.light_mode == LightMode::Bloom;
+        match cli.light_mode {
+            LightMode::Bloom => commands.entity(entity).insert(Bloom::NATURAL),
+            LightMode::Normal => commands.entity(entity).remove::<Bloom>(),
+        };
+    }
+    // start over
+    for e in cubes.drain() {
+        commands.entity(e).despawn();
+    }
+    if let Some(sys3d) = sys3d.as_mut() {
+        sys3d.clear();
+    }
+    if let Some(sparse) = sparse.as_mut() {
+        sparse.clear();
+    }
+    let amount = global_data.amount();
+    global_data.decrease(amount);
+    global_data.set_growth();
+    let handles = cube_handles(&mut meshes, &mut materials, &global_data, &glstat, &cli);
+    if let Some(spawned) = fill_full(sys3d, sparse, &glstat, &cli, &rules, &mut global_data) {
+        spawn_cubes(
+            &mut commands,
+            &mut cubes,
+            &spawned,
+            &handles,
+            &glstat,
+            cli.max_cubes,
+        );
+    }
+    eprintln!("\nPreset: {}\nRules:\n{}", preset.name, *rules);
+}
+
 pub fn adjust_timer(keyboard: Res<ButtonInput<KeyCode>>, mut sys_timer: ResMut<SystemTimer>) {
     if keyboard.just_pressed(KeyCode::KeyS) {
         sys_timer.increase_micros(31250);
@@ -354,6 +640,30 @@ pub fn manage_panorbit(
     }
 }
 
+// move the camera focus to the center of all cubes, useful in an
+// unbounded system where structures may wander off
+pub fn focus_camera(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    sparse: Option<Res<SparseSystem3d>>,
+    glstat: Res<GlobalStatic>,
+    mut pan_orbit_query: Query<&mut PanOrbitCamera>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyF) {
+        let focus = match sparse.and_then(|s| s.bounding_box()) {
+            Some((lo, hi)) => {
+                let lo = calc_spawn_coords(lo, &glstat.dims());
+                let hi = calc_spawn_coords(hi, &glstat.dims());
+                (Vec3::new(lo.0, lo.1, lo.2) + Vec3::new(hi.0, hi.1, hi.2)) / 2.0
+            }
+            None => Vec3::ZERO,
+        };
+        for mut pan_orbit in pan_orbit_query.iter_mut() {
+            pan_orbit.target_focus = focus;
+            pan_orbit.force_update = true;
+        }
+    }
+}
+
 pub fn quit(keyboard: Res<ButtonInput<KeyCode>>, mut app_exit: EventWriter<AppExit>) {
     if keyboard.just_pressed(KeyCode::KeyQ) || keyboard.just_pressed(KeyCode::Escape) {
         eprintln!("\n'Q' or 'esc' was pressed - exiting");